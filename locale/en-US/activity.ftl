@@ -0,0 +1,16 @@
+cmd_activity_top = top
+  .desc = Show the members that sent the most messages
+  .prm_period = period
+  .prm_period_desc = Only count messages sent over this period, a month by default
+  .prm_channel = channel
+  .prm_channel_desc = Only count messages sent in this channel
+cmd_activity_enable = enable
+  .desc = Start counting messages of this guild's members, contents are never stored
+cmd_activity_disable = disable
+  .desc = Stop counting messages, removing the counted ones
+cmd_activity_role = role
+  .desc = Give a role to members that were active over the last 30 days
+  .prm_role = role
+  .prm_role_desc = The role to give, leave empty to stop giving it
+  .prm_messages = messages
+  .prm_messages_desc = Messages needed over the last 30 days, 50 by default
@@ -0,0 +1,36 @@
+cmd_scrape_policy = policy
+  .desc = Choose which members get stored
+  .prm_policy = policy
+  .prm_policy_desc = Store every member, members with scraped roles, or members that opted in
+cmd_scrape_role = role
+  .desc = Add or remove a role whose members get stored under the roles policy
+  .prm_role = role
+  .prm_role_desc = The role to change
+  .prm_enabled = enabled
+  .prm_enabled_desc = Whether members with the role get stored
+cmd_scrape_optin = optin
+  .desc = Choose whether you get stored under the opt in policy
+  .prm_enabled = enabled
+  .prm_enabled_desc = Whether you get stored
+cmd_scrape_show = show
+  .desc = Show which members of this guild get stored
+cmd_whitelist_add = add
+  .desc = Whitelist a guild, so that its members get stored
+  .prm_guild = guild
+  .prm_guild_desc = The guild to whitelist
+cmd_whitelist_remove = remove
+  .desc = Remove a guild from the whitelist, along with its stored members
+  .prm_guild = guild
+  .prm_guild_desc = The guild to remove
+cmd_whitelist_list = list
+  .desc = List whitelisted guilds
+cmd_server_stats = stats
+  .desc = Show joins, leaves, retention and growth of this guild
+  .prm_days = days
+  .prm_days_desc = How many days back to look, 30 by default
+  .prm_theme = theme
+  .prm_theme_desc = Theme of the growth chart
+cmd_voice_top = top
+  .desc = Show the members that spent the most time in voice
+  .prm_period = period
+  .prm_period_desc = Only count sessions started over this period, a month by default
@@ -0,0 +1,5 @@
+paginator =
+  .not_yours = Only the user that ran this command can change pages
+  .jump = Jump to page
+  .page = Page
+  .invalid = Pick a page between 1 and { $pages }
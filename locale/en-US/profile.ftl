@@ -0,0 +1,6 @@
+cmd_profile = profile
+  .desc = Show every account linked to a user, along with a summary of each
+  .prm_user = user
+  .prm_user_desc = The user to show, yourself by default
+  .prm_theme = theme
+  .prm_theme_desc = Theme of the profile card
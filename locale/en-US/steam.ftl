@@ -4,6 +4,14 @@ cmd_steam_top = top
   .prm_by_desc = Parameter by which the top will be organized
   .prm_of = of
   .prm_of_desc = Out of which items the top will be generated
+  .prm_global = global
+  .prm_global_desc = Whether to include every guild in the top (defaults to current guild)
+  .prm_period = period
+  .prm_period_desc = Only count the playtime gained over this period
+  .prm_since = since
+  .prm_since_desc = Only count the playtime gained since this date (YYYY-MM-DD)
+  .prm_until = until
+  .prm_until_desc = End date of the custom range (YYYY-MM-DD, defaults to today)
 cmd_steam_app_top = top
   .desc = Get an app's top
   .prm_by = by
@@ -12,6 +20,12 @@ cmd_steam_app_top = top
   .prm_of_desc = Out of which items the top will be generated
   .prm_app = app
   .prm_app_desc = The target app of the top
+  .prm_period = period
+  .prm_period_desc = Only count the playtime gained over this period
+  .prm_since = since
+  .prm_since_desc = Only count the playtime gained since this date (YYYY-MM-DD)
+  .prm_until = until
+  .prm_until_desc = End date of the custom range (YYYY-MM-DD, defaults to today)
 cmd_steam_guild_top = top
   .desc = Get a guild's top
   .prm_by = by
@@ -20,17 +34,141 @@ cmd_steam_guild_top = top
   .prm_of_desc = Out of which items the top will be generated
   .prm_guild = guild
   .prm_guild_desc = The target guild of the top (defaults to current guild)
+  .prm_period = period
+  .prm_period_desc = Only count the playtime gained over this period
+  .prm_since = since
+  .prm_since_desc = Only count the playtime gained since this date (YYYY-MM-DD)
+  .prm_until = until
+  .prm_until_desc = End date of the custom range (YYYY-MM-DD, defaults to today)
 cmd_steam_user_top = top
   .desc = Get a user's top
   .prm_by = by
   .prm_by_desc = Parameter by which the top will be organized
   .prm_user = user
   .prm_user_desc = The target user of the top (defaults to you)
+  .prm_period = period
+  .prm_period_desc = Only count the playtime gained over this period
+  .prm_since = since
+  .prm_since_desc = Only count the playtime gained since this date (YYYY-MM-DD)
+  .prm_until = until
+  .prm_until_desc = End date of the custom range (YYYY-MM-DD, defaults to today)
+cmd_steam_user_history = history
+  .desc = Get a chart of a user's daily playtime
+  .prm_app = app
+  .prm_app_desc = Only include the playtime of this app
+  .prm_user = user
+  .prm_user_desc = The target user of the chart (defaults to you)
+  .prm_period = period
+  .prm_period_desc = Period the chart will cover (defaults to a month)
+  .prm_theme = theme
+  .prm_theme_desc = Color theme of the chart
+cmd_steam_app_history = history
+  .desc = Get a chart of an app's daily playtime
+  .prm_app = app
+  .prm_app_desc = The target app of the chart
+  .prm_period = period
+  .prm_period_desc = Period the chart will cover (defaults to a month)
+  .prm_theme = theme
+  .prm_theme_desc = Color theme of the chart
+cmd_steam_roles_add = add
+  .desc = Grant a role to members that own an app
+  .prm_app = app
+  .prm_app_desc = The app that grants the role
+  .prm_role = role
+  .prm_role_desc = The role that will be granted
+  .prm_min_hours = hours
+  .prm_min_hours_desc = Minimum hours played in the app to be granted the role
+cmd_steam_roles_remove = remove
+  .desc = Stop granting a role for an app
+  .prm_app = app
+  .prm_app_desc = The app that grants the role
+cmd_steam_roles_list = list
+  .desc = List roles granted for owning apps
+cmd_steam_news_subscribe = subscribe
+  .desc = Post patch notes and announcements of a game in a channel
+  .prm_app = app
+  .prm_app_desc = The game to follow
+  .prm_channel = channel
+  .prm_channel_desc = The channel news will be posted in
+cmd_steam_news_unsubscribe = unsubscribe
+  .desc = Stop posting news of a game
+  .prm_app = app
+  .prm_app_desc = The game to stop following
+  .prm_channel = channel
+  .prm_channel_desc = Only stop posting in this channel (defaults to every channel)
+cmd_steam_news_list = list
+  .desc = List games whose news are posted in this guild
+cmd_steam_milestones_setup = setup
+  .desc = Announce playtime milestones of members in a channel
+  .prm_channel = channel
+  .prm_channel_desc = The channel milestones will be announced in
+  .prm_hours = hours
+  .prm_hours_desc = Comma separated hours played in a game to celebrate (defaults to 100,500,1000)
+  .prm_games = games
+  .prm_games_desc = Amount of games owned to celebrate, 0 to disable (defaults to 1000)
+  .prm_leaders = leaders
+  .prm_leaders_desc = Whether to announce new #1s of game leaderboards (defaults to true)
+cmd_steam_milestones_disable = disable
+  .desc = Stop announcing milestones in this guild
+cmd_steam_milestones_optout = optout
+  .desc = Choose whether your own milestones get announced
+  .prm_enabled = enabled
+  .prm_enabled_desc = Whether to hide your milestones from announcements
+cmd_steam_profile = profile
+  .desc = Get a user's Steam profile
+  .prm_user = user
+  .prm_user_desc = The target user of the profile (defaults to you)
+cmd_steam_common = common
+  .desc = List games owned by all of the given users
+  .prm_first = user
+  .prm_first_desc = User to compare (against you, if no other user is given)
+  .prm_second = other
+  .prm_second_desc = Another user to compare
+  .prm_third = third
+  .prm_third_desc = Another user to compare
+  .prm_fourth = fourth
+  .prm_fourth_desc = Another user to compare
+cmd_steam_whoplays = whoplays
+  .desc = List members of this guild that own an app
+  .prm_app = app
+  .prm_app_desc = The app to look for
+
+steam_history =
+  .x = Day
+  .y = Hours played
 
 cho_by =
   .Playtime = Playtime
+  .Average = Average playtime
   .Ownership = Ownership
+  .Achievements = Achievements
+  .Completion = Completion percentage
+  .Perfect = Perfect games
 cho_of =
   .Apps = Apps
   .Guilds = Guilds
   .Users = Users
+cho_period =
+  .Day = Day
+  .Week = Week
+  .Month = Month
+  .Year = Year
+cho_theme =
+  .Dark = Dark
+  .Light = Light
+cmd_steam_watch = watch
+  .desc = Get alerted when an app goes on sale
+  .prm_app = app
+  .prm_app_desc = The app to watch
+  .prm_target = target
+  .prm_target_desc = Only alert once the price drops to this amount (defaults to any sale)
+  .prm_region = region
+  .prm_region_desc = Store region whose prices are checked (defaults to the first configured one)
+  .prm_channel = channel
+  .prm_channel_desc = Channel to be alerted in (defaults to DMs)
+cmd_steam_unwatch = unwatch
+  .desc = Stop being alerted of an app's sales
+  .prm_app = app
+  .prm_app_desc = The app to stop watching
+cmd_steam_watchlist = watchlist
+  .desc = List apps you're watching for sales
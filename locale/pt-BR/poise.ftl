@@ -0,0 +1,5 @@
+paginator =
+  .not_yours = Apenas o usuário que executou este comando pode mudar de página
+  .jump = Ir para a página
+  .page = Página
+  .invalid = Escolha uma página entre 1 e { $pages }
@@ -27,8 +27,13 @@ cmd_steam_user_top = top
   .prm_user = utilizador
   .prm_user_desc = O utilizador alvo do top (predefinido para o próprio)
 
+steam_history =
+  .x = Dia
+  .y = Horas jogadas
+
 cho_by =
   .Playtime = Tempo de jogo
+  .Average = Tempo médio de jogo
   .Ownership = Posse
 cho_of =
   .Apps = Aplicações
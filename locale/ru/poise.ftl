@@ -0,0 +1,5 @@
+paginator =
+  .not_yours = Только вызвавший команду пользователь может листать страницы
+  .jump = Перейти к странице
+  .page = Страница
+  .invalid = Выберите страницу от 1 до { $pages }
@@ -27,8 +27,13 @@ cmd_steam_user_top = топ
   .prm_user = user
   .prm_user_desc = The target user of the top (defaults to you)
 
+steam_history =
+  .x = День
+  .y = Часов сыграно
+
 cho_by =
   .Playtime = Часам игры
+  .Average = Среднему времени игры
   .Ownership = Копиям игры
 cho_of =
   .Apps = Приложений
@@ -0,0 +1,10 @@
+CREATE TABLE steam_app_achievements (
+  app_id BIGINT PRIMARY KEY REFERENCES steam_apps(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  total INTEGER NOT NULL
+);
+
+CREATE TABLE steam_playdata_achievements (
+  playdata_id BIGINT PRIMARY KEY REFERENCES steam_playdata(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  achieved INTEGER NOT NULL,
+  playtime INTEGER NOT NULL
+);
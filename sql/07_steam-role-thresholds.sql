@@ -0,0 +1 @@
+ALTER TABLE steam_discord_roles ADD COLUMN min_playtime INTEGER NOT NULL DEFAULT 0;
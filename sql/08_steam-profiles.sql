@@ -0,0 +1,2 @@
+ALTER TABLE steam_users ADD COLUMN profile_url TEXT;
+ALTER TABLE steam_users ADD COLUMN visibility INTEGER;
@@ -0,0 +1,8 @@
+-- Not referencing steam_users, as accounts that were not found never get a row there
+CREATE TABLE steam_fetch_state (
+  steam_id BIGINT PRIMARY KEY,
+  state TEXT NOT NULL,
+  error TEXT,
+  checked_at BIGINT NOT NULL,
+  notified BOOLEAN NOT NULL DEFAULT FALSE
+);
@@ -0,0 +1,7 @@
+ALTER TABLE steam_fetch_state ADD COLUMN full_at BIGINT;
+
+CREATE TABLE steam_update_runs (
+  kind TEXT PRIMARY KEY,
+  started_at BIGINT NOT NULL,
+  finished_at BIGINT
+);
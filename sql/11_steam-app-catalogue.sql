@@ -0,0 +1,6 @@
+ALTER TABLE steam_apps ADD COLUMN app_type TEXT;
+ALTER TABLE steam_apps ADD COLUMN release_day INTEGER;
+ALTER TABLE steam_apps ADD COLUMN last_modified BIGINT;
+ALTER TABLE steam_apps ADD COLUMN details_at BIGINT;
+
+CREATE INDEX steam_apps_last_modified ON steam_apps(last_modified);
@@ -0,0 +1,19 @@
+CREATE TABLE steam_milestone_settings (
+  guild_id BIGINT PRIMARY KEY,
+  channel_id BIGINT NOT NULL,
+  -- Comma separated playtime thresholds in hours
+  hours TEXT NOT NULL,
+  games INTEGER NOT NULL,
+  leaders BOOLEAN NOT NULL
+);
+
+CREATE TABLE steam_milestone_optout (
+  discord_id BIGINT PRIMARY KEY
+);
+
+CREATE TABLE steam_app_leaders (
+  guild_id BIGINT NOT NULL,
+  app_id BIGINT NOT NULL REFERENCES steam_apps(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  discord_id BIGINT NOT NULL,
+  PRIMARY KEY (guild_id, app_id)
+);
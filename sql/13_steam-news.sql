@@ -0,0 +1,16 @@
+CREATE TABLE steam_news_subscriptions (
+  channel_id BIGINT NOT NULL,
+  app_id BIGINT NOT NULL REFERENCES steam_apps(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  guild_id BIGINT NOT NULL,
+  PRIMARY KEY (channel_id, app_id)
+);
+
+CREATE INDEX steam_news_subscriptions_guild_id ON steam_news_subscriptions(guild_id);
+
+-- News items that have already been posted, or were published before the app got its first subscriber
+CREATE TABLE steam_news_seen (
+  app_id BIGINT NOT NULL REFERENCES steam_apps(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  gid TEXT NOT NULL,
+  published_at BIGINT NOT NULL,
+  PRIMARY KEY (app_id, gid)
+);
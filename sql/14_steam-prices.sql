@@ -0,0 +1,24 @@
+-- Appended whenever the price of an app changes in a region
+CREATE TABLE steam_price_history (
+  app_id BIGINT NOT NULL REFERENCES steam_apps(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  region TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  initial INTEGER NOT NULL,
+  price INTEGER NOT NULL,
+  discount INTEGER NOT NULL,
+  checked_at BIGINT NOT NULL,
+  PRIMARY KEY (app_id, region, checked_at)
+);
+
+CREATE TABLE steam_price_watches (
+  discord_id BIGINT NOT NULL,
+  app_id BIGINT NOT NULL REFERENCES steam_apps(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  region TEXT NOT NULL,
+  -- In cents, alerts on any sale if unset
+  target INTEGER,
+  -- Alerts are sent as DMs if unset
+  channel_id BIGINT,
+  -- Set while the alert condition holds, so that it's only sent once
+  alerted BOOLEAN NOT NULL DEFAULT FALSE,
+  PRIMARY KEY (discord_id, app_id)
+);
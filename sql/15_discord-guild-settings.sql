@@ -0,0 +1,28 @@
+-- Which members of a whitelisted guild get scraped, guilds without settings scrape everyone
+CREATE TABLE discord_guild_settings (
+  guild_id BIGINT PRIMARY KEY,
+  -- One of 'all', 'roles' or 'optin'
+  policy TEXT NOT NULL
+);
+
+-- Members with any of these roles are scraped under the 'roles' policy
+CREATE TABLE discord_scrape_roles (
+  guild_id BIGINT NOT NULL,
+  role_id BIGINT NOT NULL,
+  PRIMARY KEY (guild_id, role_id)
+);
+
+-- Members that opted in are scraped under the 'optin' policy
+CREATE TABLE discord_scrape_optin (
+  guild_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  PRIMARY KEY (guild_id, user_id)
+);
+
+-- Keep the previously hard-coded filters of already whitelisted guilds
+INSERT INTO discord_guild_settings (guild_id, policy)
+  SELECT guild_id, CASE WHEN guild_id = 1404602275401568347 THEN 'all' ELSE 'roles' END
+  FROM neko_whitelist_discord;
+INSERT INTO discord_scrape_roles (guild_id, role_id)
+  SELECT guild_id, 1232817578037084262 FROM neko_whitelist_discord
+  WHERE guild_id <> 1404602275401568347;
@@ -0,0 +1,5 @@
+-- Non-whitelisted guilds the bot joined, left once the grace period passes
+CREATE TABLE discord_whitelist_requests (
+  guild_id BIGINT PRIMARY KEY,
+  requested_at BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Members not seen by a full member sync of their guild get removed, instead of pruning every guild on startup
+ALTER TABLE discord_members ADD COLUMN synced_gen BIGINT NOT NULL DEFAULT 0;
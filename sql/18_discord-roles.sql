@@ -0,0 +1,18 @@
+CREATE TABLE discord_roles (
+  id BIGINT PRIMARY KEY,
+  guild_id BIGINT NOT NULL REFERENCES discord_guilds(id) ON DELETE CASCADE ON UPDATE CASCADE,
+  name VARCHAR(100) NOT NULL,
+  color INTEGER NOT NULL,
+  position BIGINT NOT NULL
+);
+
+-- Not referencing discord_roles, as member events can arrive before the role ones
+CREATE TABLE discord_member_roles (
+  guild_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  role_id BIGINT NOT NULL,
+  PRIMARY KEY (guild_id, user_id, role_id),
+  FOREIGN KEY (guild_id, user_id) REFERENCES discord_members(guild_id, user_id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX discord_member_roles_role_id ON discord_member_roles(role_id);
@@ -0,0 +1,11 @@
+-- Not referencing discord_members, so that the history outlives the members
+CREATE TABLE discord_member_events (
+  id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+  guild_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  kind TEXT NOT NULL CHECK (kind IN ('join', 'leave', 'nick', 'roles')),
+  detail TEXT,
+  created_at BIGINT NOT NULL
+);
+
+CREATE INDEX discord_member_events_guild_id ON discord_member_events(guild_id, created_at);
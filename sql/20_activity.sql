@@ -0,0 +1,17 @@
+-- Guilds that opted in to activity tracking, along with their optional auto role
+CREATE TABLE activity_guilds (
+  guild_id BIGINT PRIMARY KEY,
+  role_id BIGINT,
+  -- Messages within the last 30 days needed for the role
+  role_messages INTEGER NOT NULL DEFAULT 0
+);
+
+-- Message counts only, no content is stored
+CREATE TABLE activity_messages (
+  guild_id BIGINT NOT NULL REFERENCES activity_guilds(guild_id) ON DELETE CASCADE ON UPDATE CASCADE,
+  channel_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  utc_day INTEGER NOT NULL,
+  count INTEGER NOT NULL,
+  PRIMARY KEY (guild_id, utc_day, channel_id, user_id)
+);
@@ -0,0 +1,14 @@
+-- Not referencing discord_members, so that the history outlives the members
+CREATE TABLE discord_voice_sessions (
+  id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+  guild_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  channel_id BIGINT NOT NULL,
+  joined_at BIGINT NOT NULL,
+  -- Unset while the member is still in the channel
+  left_at BIGINT
+);
+
+CREATE INDEX discord_voice_sessions_guild_id ON discord_voice_sessions(guild_id, joined_at);
+CREATE INDEX discord_voice_sessions_user_id ON discord_voice_sessions(user_id, joined_at);
+CREATE INDEX discord_voice_sessions_open ON discord_voice_sessions(guild_id, user_id) WHERE left_at IS NULL;
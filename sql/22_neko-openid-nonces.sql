@@ -0,0 +1,5 @@
+-- Steam OpenID response nonces that were already used, kept until they'd be rejected as too old anyway
+CREATE TABLE neko_openid_nonces (
+  nonce TEXT PRIMARY KEY,
+  used_at BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Achievement schemas are refetched periodically, as updates can add achievements to an app
+ALTER TABLE steam_app_achievements ADD COLUMN checked_at BIGINT NOT NULL DEFAULT 0;
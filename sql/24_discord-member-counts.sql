@@ -0,0 +1,17 @@
+-- Joins and leaves of each UTC day, counted for every member regardless of the scrape policy
+CREATE TABLE discord_member_counts (
+  guild_id BIGINT NOT NULL,
+  utc_day BIGINT NOT NULL,
+  joins INTEGER NOT NULL DEFAULT 0,
+  leaves INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (guild_id, utc_day)
+);
+
+-- Start from the events recorded so far
+INSERT INTO discord_member_counts (guild_id, utc_day, joins, leaves)
+SELECT guild_id, created_at / 86400,
+  COUNT(*) FILTER (WHERE kind = 'join'),
+  COUNT(*) FILTER (WHERE kind = 'leave')
+FROM discord_member_events
+WHERE kind IN ('join', 'leave')
+GROUP BY guild_id, created_at / 86400;
@@ -0,0 +1,9 @@
+-- Close all but the latest open session of each member, so only one can be open at a time
+UPDATE discord_voice_sessions s SET left_at = extract(epoch FROM now())::BIGINT
+WHERE s.left_at IS NULL AND EXISTS (
+  SELECT 1 FROM discord_voice_sessions o
+  WHERE o.guild_id = s.guild_id AND o.user_id = s.user_id AND o.left_at IS NULL AND o.id > s.id
+);
+
+DROP INDEX discord_voice_sessions_open;
+CREATE UNIQUE INDEX discord_voice_sessions_open ON discord_voice_sessions(guild_id, user_id) WHERE left_at IS NULL;
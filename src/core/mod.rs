@@ -9,6 +9,7 @@ pub use framework::*;
 mod module;
 pub use module::*;
 mod state;
+pub mod time;
 
 pub type Err = Box<dyn Error + Send + Sync>;
 pub type Res<T> = Result<T, Err>;
@@ -0,0 +1,99 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::Res;
+use chrono::{NaiveDate, Utc};
+use poise::ChoiceParameter;
+
+// Time helpers shared by plugins
+
+#[derive(ChoiceParameter, PartialEq)]
+pub enum Period {
+  Day,
+  Week,
+  Month,
+  Year,
+}
+
+impl Period {
+  pub fn span(&self) -> Span {
+    let to = (Utc::now().timestamp() / 86400) as i32;
+    let days = match self {
+      Period::Day => 1,
+      Period::Week => 7,
+      Period::Month => 30,
+      Period::Year => 365,
+    };
+    Span {
+      from: to - days + 1,
+      to,
+    }
+  }
+}
+
+/// Inclusive range of UTC days, counted from the unix epoch like `steam_playdata_history.utc_day`
+#[derive(Clone, Copy)]
+pub struct Span {
+  pub from: i32,
+  pub to: i32,
+}
+
+impl Span {
+  /// Parse a custom range out of `YYYY-MM-DD` dates, defaulting the end to today
+  pub fn parse(from: &str, to: Option<&str>) -> Res<Self> {
+    let day = |date: &str| -> Res<i32> {
+      let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).ok_or("Invalid epoch")?;
+      let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+      Ok(date.signed_duration_since(epoch).num_days() as i32)
+    };
+    let span = Span {
+      from: day(from)?,
+      to: match to {
+        Some(to) => day(to)?,
+        None => (Utc::now().timestamp() / 86400) as i32,
+      },
+    };
+    if span.from > span.to {
+      return Err("Start of the range must be before its end".into());
+    }
+    Ok(span)
+  }
+}
+
+fn divdec(f: i64, s: i64) -> (i64, i64) {
+  (f / s, f * 10 / s % 10)
+}
+
+/// Format seconds in the largest fitting unit, with one decimal while below 10 of it
+pub fn fmt_sec(num: i64) -> String {
+  let (mf, md) = divdec(num, 60);
+  let (hf, hd) = divdec(num, 3600);
+  let (df, dd) = divdec(num, 86400);
+  let (wf, wd) = divdec(num, 604800);
+  let (yf, yd) = divdec(num, 31556926);
+
+  if mf < 1 {
+    format!("{num}s")
+  } else if mf < 10 {
+    format!("{mf}.{md}m")
+  } else if hf < 1 {
+    format!("{mf}m")
+  } else if hf < 10 {
+    format!("{hf}.{hd}h")
+  } else if df < 1 {
+    format!("{hf}h")
+  } else if df < 10 {
+    format!("{df}.{dd}d")
+  } else if wf < 1 {
+    format!("{df}d")
+  } else if wf < 10 {
+    format!("{wf}.{wd}w")
+  } else if yf < 1 {
+    format!("{wf}w")
+  } else if yf < 10 {
+    format!("{yf}.{yd}y")
+  } else {
+    format!("{yf}y")
+  }
+}
@@ -14,6 +14,8 @@ pub mod modules {
   automod::dir!(pub "src/modules");
 }
 pub mod plugins {
+  #[path="activity/plugin.rs"]
+  pub mod activity;
   #[path="atakku/plugin.rs"]
   pub mod atakku;
   #[path="beatleader/plugin.rs"]
@@ -30,6 +32,8 @@ pub mod plugins {
   pub mod neko;
   //#[path="radio/plugin.rs"]
   //pub mod radio;
+  #[path="profile/plugin.rs"]
+  pub mod profile;
   #[path="steam/plugin.rs"]
   pub mod steam;
   //#[path="warnsys/plugin.rs"]
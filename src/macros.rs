@@ -83,7 +83,9 @@ macro_rules! api {
     )*
   }) => {
     pub trait $name {
-      $(fn $fun(&self, $($($pn: $pt),*)?) -> impl std::future::Future<Output = crate::core::Res<$ty>>;)*
+      // Parameters mirror the query string of the endpoint
+      $(#[allow(clippy::too_many_arguments)]
+      fn $fun(&self, $($($pn: $pt),*)?) -> impl std::future::Future<Output = crate::core::Res<$ty>>;)*
     }
 
     impl $name for reqwest::Client {
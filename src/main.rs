@@ -13,11 +13,13 @@ async fn main() -> R {
   let mut fw = Framework::new();
     fw.init_module(atakku::Atakku).await?;
     fw.init_module(discord::Discord).await?;
+    fw.init_module(activity::Activity).await?;
     fw.init_module(steam::Steam).await?;
     fw.init_module(drg::DeepRockGalactic).await?;
-    fw.init_module(gwaaa::Gwaaa {}).await?;
+    fw.init_module(gwaaa::Gwaaa::default()).await?;
     //fw.init_module(warnsys::WarnSystem {}).await?;
     fw.init_module(beatleader::BeatLeader {}).await?;
+    fw.init_module(profile::Profile).await?;
     //fw.init_module(radio::Radio {}).await?;
     fw.init_module(welcomer::Welcomer).await?;
     fw.init_module(ftvroles::FTVRoles).await?;
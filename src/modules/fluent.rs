@@ -49,6 +49,8 @@ impl Module for Fluent {
         let mut bundles = HashMap::new();
         for (locale, res) in this.resources {
           let mut bundle = FluentBundle::new_concurrent(vec![locale.parse()?]);
+          // Isolation marks around placeables show up as garbage in Discord and rendered images
+          bundle.set_use_isolating(false);
           for r in res {
             bundle
               .add_resource(r)
@@ -105,3 +107,21 @@ pub fn localize<'a>(
   };
   Some(bun.format_pattern(pattern, args, &mut vec![]).into())
 }
+
+/// Localize a message at runtime, falling back to the default locale, and then to the id itself
+pub fn tr(
+  locale: Option<&str>,
+  id: &str,
+  attr: Option<&str>,
+  args: Option<&FluentArgs<'_>>,
+) -> String {
+  let fb = loc();
+  locale
+    .and_then(|l| fb.bundles.get(l))
+    .and_then(|bun| localize(bun, id, attr, args))
+    .or_else(|| localize(fb.bundles.get(&fb.default)?, id, attr, args))
+    .unwrap_or_else(|| match attr {
+      Some(attr) => format!("{id}.{attr}"),
+      None => id.into(),
+    })
+}
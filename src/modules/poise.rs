@@ -4,14 +4,21 @@
 
 use crate::{
   core::*,
-  modules::fluent::{loc, localize, Fluent, FluentBundle, FluentBundles},
+  modules::fluent::{loc, localize, tr, Fluent, FluentBundle, FluentBundles},
 };
 use derivative::Derivative;
-use futures::future::join_all;
+use fluent::FluentArgs;
+use futures::{future::join_all, Future};
 use poise::{
-  serenity_prelude::{Context as SCtx, GatewayIntents},
+  serenity_prelude::{
+    ActionRowComponent, ButtonStyle, CollectComponentInteraction, CollectModalInteraction,
+    Context as SCtx, CreateComponents, CreateInteractionResponse, GatewayIntents, InputTextStyle,
+    InteractionResponseType,
+  },
   BoxFuture, Command, Context, Event, FrameworkContext, FrameworkOptions,
 };
+use sea_query::SelectStatement;
+use std::time::Duration;
 
 pub type Fw = poise::Framework<Vec<EventHandler>, Err>;
 pub type FwCtx<'a> = FrameworkContext<'a, Vec<EventHandler>, Err>;
@@ -22,6 +29,14 @@ pub type EventHandler = for<'a> fn(&'a SCtx, &'a Event<'a>) -> BoxFuture<'a, R>;
 // TODO: add documentation,
 // Poise wrapper module, to let other modules add commands and subscribe to events easily
 
+// Serenity context, for tasks that run outside of commands and events (cron jobs, web callbacks)
+once_cell!(sctx, SCTX: SCtx);
+
+/// Serenity context, unless the bot hasn't connected yet
+pub fn try_sctx() -> Option<&'static SCtx> {
+  SCTX.get()
+}
+
 #[derive(Derivative)]
 #[derivative(Default)]
 pub struct Poise {
@@ -52,7 +67,12 @@ impl Module for Poise {
               },
               ..Default::default()
             })
-            .setup(move |_c, _r, _f| Box::pin(async move { Ok(m.event_handlers) }))
+            .setup(move |c, _r, _f| {
+              Box::pin(async move {
+                let _ = SCTX.set(c.clone());
+                Ok(m.event_handlers)
+              })
+            })
             .run()
             .await?;
           Ok(())
@@ -169,3 +189,173 @@ fn get_loc<'a>(
   }
   return None;
 }
+
+// Buttons stop working after this long without being pressed
+const PAGINATOR_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Send a message with buttons to browse its pages, the amount of pages is counted with `total`
+/// and each page is rendered by `page`, only the user that ran the command can change pages
+pub async fn paginate<F, Fut>(ctx: Ctx<'_>, total: SelectStatement, size: u64, page: F) -> R
+where
+  F: Fn(u64) -> Fut,
+  Fut: Future<Output = Res<String>>,
+{
+  // Counting and rendering the first page can take longer than the interaction allows
+  ctx.defer().await?;
+  let (count,) = fetch_one!(&total, (i64,))?;
+  let pages = (count.max(0) as u64).div_ceil(size).max(1);
+  let mut current = 0;
+  let content = page(current).await?;
+  // Pages list users as mentions, which shouldn't ping anyone
+  if pages == 1 {
+    ctx
+      .send(|b| b.content(content).allowed_mentions(|a| a.empty_parse()))
+      .await?;
+    return Ok(());
+  }
+  let mut msg = ctx
+    .send(|b| {
+      b.content(content)
+        .allowed_mentions(|a| a.empty_parse())
+        .components(|c| page_buttons(c, current, pages))
+    })
+    .await?
+    .into_message()
+    .await?;
+
+  let locale = ctx.locale();
+  let jump_id = format!("pg_jump_{}", msg.id);
+  while let Some(press) = CollectComponentInteraction::new(ctx)
+    .message_id(msg.id)
+    .timeout(PAGINATOR_TIMEOUT)
+    .await
+  {
+    if press.user.id != ctx.author().id {
+      press
+        .create_interaction_response(ctx, |r| {
+          r.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|d| {
+              d.ephemeral(true)
+                .content(tr(locale, "paginator", Some("not_yours"), None))
+            })
+        })
+        .await?;
+      continue;
+    }
+    current = match press.data.custom_id.as_str() {
+      "pg_first" => 0,
+      "pg_prev" => current.saturating_sub(1),
+      "pg_next" => (current + 1).min(pages - 1),
+      "pg_last" => pages - 1,
+      "pg_jump" => {
+        press
+          .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::Modal)
+              .interaction_response_data(|d| {
+                d.custom_id(&jump_id)
+                  .title(tr(locale, "paginator", Some("jump"), None))
+                  .components(|c| {
+                    c.create_action_row(|r| {
+                      r.create_input_text(|t| {
+                        t.custom_id("page")
+                          .label(tr(locale, "paginator", Some("page"), None))
+                          .placeholder(format!("1-{pages}"))
+                          .style(InputTextStyle::Short)
+                          .required(true)
+                      })
+                    })
+                  })
+              })
+          })
+          .await?;
+        let id = jump_id.clone();
+        let Some(modal) = CollectModalInteraction::new(ctx)
+          .author_id(ctx.author().id)
+          .filter(move |m| m.data.custom_id == id)
+          .timeout(PAGINATOR_TIMEOUT)
+          .await
+        else {
+          continue;
+        };
+        let input = modal
+          .data
+          .components
+          .iter()
+          .flat_map(|r| &r.components)
+          .find_map(|c| match c {
+            ActionRowComponent::InputText(t) => Some(t.value.clone()),
+            _ => None,
+          });
+        match input.and_then(|i| i.trim().parse::<u64>().ok()) {
+          Some(target) if (1..=pages).contains(&target) => {
+            current = target - 1;
+            let content = page(current).await?;
+            modal
+              .create_interaction_response(ctx, |r| update_page(r, content, current, pages))
+              .await?;
+          }
+          _ => {
+            // FluentArgs isn't Sync, so it can't live across the await
+            let content = {
+              let mut args = FluentArgs::new();
+              args.set("pages", pages);
+              tr(locale, "paginator", Some("invalid"), Some(&args))
+            };
+            modal
+              .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::ChannelMessageWithSource)
+                  .interaction_response_data(|d| d.ephemeral(true).content(content))
+              })
+              .await?;
+          }
+        }
+        continue;
+      }
+      _ => continue,
+    };
+    let content = page(current).await?;
+    press
+      .create_interaction_response(ctx, |r| update_page(r, content, current, pages))
+      .await?;
+  }
+  // The message might be gone by now, which is fine
+  if let Err(err) = msg.edit(ctx, |m| m.components(|c| c)).await {
+    log::debug!("Failed to remove page buttons of {}: {err}", msg.id);
+  }
+  Ok(())
+}
+
+fn update_page<'a, 'b>(
+  r: &'b mut CreateInteractionResponse<'a>,
+  content: String,
+  current: u64,
+  pages: u64,
+) -> &'b mut CreateInteractionResponse<'a> {
+  r.kind(InteractionResponseType::UpdateMessage)
+    .interaction_response_data(|d| {
+      d.content(content)
+        .allowed_mentions(|a| a.empty_parse())
+        .components(|c| page_buttons(c, current, pages))
+    })
+}
+
+fn page_buttons(c: &mut CreateComponents, current: u64, pages: u64) -> &mut CreateComponents {
+  let last = pages - 1;
+  c.create_action_row(|r| {
+    for (id, label, disabled) in [
+      ("pg_first", "«".to_string(), current == 0),
+      ("pg_prev", "‹".to_string(), current == 0),
+      ("pg_jump", format!("{}/{pages}", current + 1), false),
+      ("pg_next", "›".to_string(), current == last),
+      ("pg_last", "»".to_string(), current == last),
+    ] {
+      r.create_button(|b| {
+        b.custom_id(id)
+          .label(label)
+          .style(ButtonStyle::Secondary)
+          .disabled(disabled)
+      });
+    }
+    r
+  })
+}
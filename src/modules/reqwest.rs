@@ -2,9 +2,11 @@
 //
 // This project is dual licensed under MIT and Apache.
 
-use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
 use reqwest::cookie::Jar;
 use reqwest::Client;
+use tokio::time::Instant;
 
 once_cell!(req, CLIENT: Client);
 
@@ -31,3 +33,29 @@ impl crate::core::Module for Reqwest {
     Ok(())
   }
 }
+
+/// Request budget shared between tasks, spacing requests at least `interval` apart
+pub struct RateLimit {
+  interval: Duration,
+  next: Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+  pub const fn new(interval: Duration) -> Self {
+    Self {
+      interval,
+      next: Mutex::new(None),
+    }
+  }
+
+  /// Reserve the next free slot of the budget, and wait until it comes
+  pub async fn wait(&self) {
+    let slot = {
+      let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+      let slot = next.map_or(Instant::now(), |n| n.max(Instant::now()));
+      *next = Some(slot + self.interval);
+      slot
+    };
+    tokio::time::sleep_until(slot).await;
+  }
+}
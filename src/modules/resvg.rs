@@ -0,0 +1,197 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::core::*;
+use poise::ChoiceParameter;
+use resvg::{
+  tiny_skia,
+  usvg::{self, fontdb, TreeParsing, TreeTextToPath},
+};
+
+once_cell!(fonts, FONTS: fontdb::Database);
+
+/// Resvg module, loads system fonts once so that plugins can rasterize svgs containing text
+#[derive(Default)]
+pub struct Resvg;
+
+impl Module for Resvg {
+  async fn init(&mut self, fw: &mut Framework) -> R {
+    runtime!(fw, |_m| {
+      let mut db = fontdb::Database::new();
+      db.load_system_fonts();
+      log::info!("Loaded {} font faces", db.len());
+      FONTS.set(db)?;
+      Ok(None)
+    });
+    Ok(())
+  }
+}
+
+pub fn render_png(svg: &str) -> Res<Vec<u8>> {
+  let mut tree = usvg::Tree::from_str(svg, &usvg::Options::default())?;
+  tree.convert_text(fonts());
+  let rtree = resvg::Tree::from_usvg(&tree);
+  let size = rtree.size.to_int_size();
+  let mut pixmap =
+    tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("Failed to allocate pixmap")?;
+  rtree.render(tiny_skia::Transform::default(), &mut pixmap.as_mut());
+  Ok(pixmap.encode_png()?)
+}
+
+pub fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+#[derive(ChoiceParameter, PartialEq, Clone, Copy)]
+pub enum Theme {
+  Dark,
+  Light,
+}
+
+pub struct Palette {
+  pub background: &'static str,
+  pub foreground: &'static str,
+  pub grid: &'static str,
+  pub accent: &'static str,
+}
+
+impl Theme {
+  // Same colors as the ones used on the website
+  pub fn palette(&self) -> Palette {
+    match self {
+      Theme::Dark => Palette {
+        background: "#1e293b",
+        foreground: "#fbcfe8",
+        grid: "#475569",
+        accent: "#e91e63",
+      },
+      Theme::Light => Palette {
+        background: "#ffffff",
+        foreground: "#1e293b",
+        grid: "#cbd5e1",
+        accent: "#e91e63",
+      },
+    }
+  }
+}
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 400.0;
+// Charts with more points than this are drawn as lines instead of bars
+const MAX_BARS: usize = 31;
+
+/// Simple single series chart, rendered as bars or as a line depending on the amount of points
+pub struct Chart {
+  pub theme: Theme,
+  pub title: String,
+  pub x_label: String,
+  pub y_label: String,
+  pub points: Vec<(String, f64)>,
+}
+
+impl Chart {
+  pub fn to_svg(&self) -> String {
+    let p = self.theme.palette();
+    let (left, right, top, bottom) = (72.0, WIDTH - 24.0, 48.0, HEIGHT - 64.0);
+    let max = nice_max(self.points.iter().map(|p| p.1).fold(0.0, f64::max));
+    let count = self.points.len().max(1);
+    let step = (right - left) / count as f64;
+    let y = |v: f64| bottom - v / max * (bottom - top);
+
+    let mut svg = format!(
+      "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+      font-family=\"sans-serif\" fill=\"{}\">\
+      <rect width=\"{WIDTH}\" height=\"{HEIGHT}\" fill=\"{}\"/>\
+      <text x=\"{}\" y=\"32\" font-size=\"20\" font-weight=\"bold\" text-anchor=\"middle\">{}</text>",
+      p.foreground,
+      p.background,
+      WIDTH / 2.0,
+      escape(&self.title)
+    );
+    for i in 0..=4 {
+      let v = max * i as f64 / 4.0;
+      svg += &format!(
+        "<line x1=\"{left}\" y1=\"{0}\" x2=\"{right}\" y2=\"{0}\" stroke=\"{1}\"/>\
+        <text x=\"{2}\" y=\"{3}\" font-size=\"12\" text-anchor=\"end\">{4}</text>",
+        y(v),
+        p.grid,
+        left - 8.0,
+        y(v) + 4.0,
+        fmt_value(v)
+      );
+    }
+    if self.points.len() <= MAX_BARS {
+      for (i, (_, v)) in self.points.iter().enumerate() {
+        svg += &format!(
+          "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+          left + step * (i as f64 + 0.15),
+          y(*v),
+          step * 0.7,
+          bottom - y(*v),
+          p.accent
+        );
+      }
+    } else {
+      let line: Vec<_> = (self.points.iter().enumerate())
+        .map(|(i, (_, v))| format!("{},{}", left + step * (i as f64 + 0.5), y(*v)))
+        .collect();
+      svg += &format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+        line.join(" "),
+        p.accent
+      );
+    }
+    // Only label every n-th point, so that labels don't overlap
+    let every = self.points.len().div_ceil(8);
+    for (i, (label, _)) in self.points.iter().enumerate().step_by(every.max(1)) {
+      svg += &format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\">{}</text>",
+        left + step * (i as f64 + 0.5),
+        bottom + 18.0,
+        escape(label)
+      );
+    }
+    svg += &format!(
+      "<text x=\"{}\" y=\"{}\" font-size=\"14\" text-anchor=\"middle\">{}</text>\
+      <text transform=\"translate(20 {}) rotate(-90)\" font-size=\"14\" text-anchor=\"middle\">{}</text>\
+      </svg>",
+      (left + right) / 2.0,
+      HEIGHT - 16.0,
+      escape(&self.x_label),
+      (top + bottom) / 2.0,
+      escape(&self.y_label)
+    );
+    svg
+  }
+
+  pub fn render(&self) -> Res<Vec<u8>> {
+    render_png(&self.to_svg())
+  }
+}
+
+// Round the top of the y axis up to 1, 2, 2.5 or 5 times a power of ten
+fn nice_max(max: f64) -> f64 {
+  if max <= 0.0 {
+    return 1.0;
+  }
+  let mag = 10f64.powf(max.log10().floor());
+  for m in [1.0, 2.0, 2.5, 5.0] {
+    if m * mag >= max {
+      return m * mag;
+    }
+  }
+  10.0 * mag
+}
+
+fn fmt_value(v: f64) -> String {
+  if v.fract() == 0.0 {
+    format!("{v:.0}")
+  } else {
+    format!("{v:.1}")
+  }
+}
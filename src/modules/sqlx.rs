@@ -4,6 +4,7 @@
 
 use crate::core::*;
 use derivative::Derivative;
+use sea_query::{Alias, Asterisk, Expr, Func, Query, SelectStatement};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 
 once_cell!(db, POOL: PgPool);
@@ -27,3 +28,11 @@ impl Module for Postgres {
     Ok(())
   }
 }
+
+/// Query counting the rows that the given query returns
+pub fn count_rows(qb: SelectStatement) -> SelectStatement {
+  let mut count = Query::select();
+  count.from_subquery(qb, Alias::new("rows"));
+  count.expr(Func::count(Expr::col(Asterisk)));
+  count
+}
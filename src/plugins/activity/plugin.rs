@@ -0,0 +1,395 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use self::schema::*;
+use crate::{
+  core::{time::Period, *},
+  modules::{
+    cron::Cron,
+    poise::{paginate, try_sctx, Ctx, EventHandler, Poise},
+    sqlx::{count_rows, Postgres},
+  },
+};
+use chrono::Utc;
+use futures::StreamExt;
+use poise::{
+  serenity_prelude::{Context, GuildChannel, GuildId, Message, Permissions, Role},
+  Event,
+};
+use sea_query::{Alias, Expr, Func, OnConflict, Order, Query};
+use std::{
+  collections::{HashMap, HashSet},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, RwLock,
+  },
+};
+use tokio_cron_scheduler::Job;
+
+pub mod schema;
+
+/// Counts messages of members in guilds that opted in, without storing any content
+pub struct Activity;
+
+impl Module for Activity {
+  async fn init(&mut self, fw: &mut Framework) -> R {
+    fw.req_module::<Postgres>().await?;
+    let poise = fw.req_module::<Poise>().await?;
+    poise.commands.push(activity());
+    poise.event_handlers.push(event_handler());
+    cron!(fw, "0 * * * * *", || {
+      if let Err(err) = flush_counts().await {
+        log::warn!("Failed to store activity counts: {err}");
+      }
+    });
+    cron!(fw, "0 15 * * * *", || {
+      if let Some(c) = try_sctx() {
+        if let Err(err) = sync_roles(c).await {
+          log::warn!("Failed to sync activity roles: {err}");
+        }
+      }
+    });
+    Ok(())
+  }
+}
+
+const SIZE: u64 = 15;
+
+// Window of the auto role, "active this month"
+const ROLE_DAYS: i32 = 30;
+
+fn event_handler() -> EventHandler {
+  |_c, event| {
+    Box::pin(async move {
+      if let Event::Message { new_message: m } = event {
+        if let Some(guild) = m.guild_id {
+          if !m.author.bot && m.webhook_id.is_none() && check_enabled(guild).await? {
+            count_message(guild, m);
+          }
+        }
+      }
+      Ok(())
+    })
+  }
+}
+
+// Guilds that opted in, loaded on first use and dropped whenever one opts in or out
+static ENABLED: RwLock<Option<HashSet<GuildId>>> = RwLock::new(None);
+// Bumped on every change, so a load that raced with one doesn't get cached
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+async fn enabled_guilds() -> Res<HashSet<GuildId>> {
+  use Guilds::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(GuildId);
+  Ok(
+    fetch_all!(&qb, (i64,))?
+      .into_iter()
+      .map(|g| poise::serenity_prelude::GuildId(g.0 as u64))
+      .collect(),
+  )
+}
+
+async fn check_enabled(id: GuildId) -> Res<bool> {
+  let cached = (ENABLED.read().unwrap_or_else(|e| e.into_inner()))
+    .as_ref()
+    .map(|c| c.contains(&id));
+  if let Some(enabled) = cached {
+    return Ok(enabled);
+  }
+  let generation = GENERATION.load(Ordering::SeqCst);
+  let guilds = enabled_guilds().await?;
+  let enabled = guilds.contains(&id);
+  let mut cache = ENABLED.write().unwrap_or_else(|e| e.into_inner());
+  if GENERATION.load(Ordering::SeqCst) == generation {
+    *cache = Some(guilds);
+  }
+  Ok(enabled)
+}
+
+// Guild, channel, user and day of a message
+type CountKey = (i64, i64, i64, i32);
+
+// Counts not yet written
+static COUNTS: Mutex<Option<HashMap<CountKey, i32>>> = Mutex::new(None);
+
+fn count_message(guild: GuildId, m: &Message) {
+  let day = (m.timestamp.unix_timestamp() / 86400) as i32;
+  let key = (
+    guild.0 as i64,
+    m.channel_id.0 as i64,
+    m.author.id.0 as i64,
+    day,
+  );
+  let mut counts = COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+  *counts
+    .get_or_insert_with(HashMap::new)
+    .entry(key)
+    .or_default() += 1;
+}
+
+/// Add the buffered counts to the daily aggregates
+pub async fn flush_counts() -> R {
+  let Some(mut counts) = COUNTS.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+    return Ok(());
+  };
+  // Counts of guilds that opted out since would fail the foreign key, along with every other count
+  let enabled = enabled_guilds().await?;
+  counts.retain(|k, _| enabled.contains(&poise::serenity_prelude::GuildId(k.0 as u64)));
+  if counts.is_empty() {
+    return Ok(());
+  }
+  use Messages::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, ChannelId, UserId, UtcDay, Count]);
+  for ((guild, channel, user, day), count) in &counts {
+    qb.values([
+      (*guild).into(),
+      (*channel).into(),
+      (*user).into(),
+      (*day).into(),
+      (*count).into(),
+    ])?;
+  }
+  qb.on_conflict(
+    OnConflict::columns([GuildId, UtcDay, ChannelId, UserId])
+      .value(
+        Count,
+        Expr::col((Table, Count)).add(Expr::col((Alias::new("excluded"), Count))),
+      )
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  log::trace!("Stored {} activity counts", counts.len());
+  Ok(())
+}
+
+// Members with at least `messages` messages in the role window
+async fn active_members(guild: i64, messages: i32) -> Res<HashSet<i64>> {
+  use Messages::*;
+  let to = (Utc::now().timestamp() / 86400) as i32;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(UserId);
+  qb.and_where(Expr::col(GuildId).eq(guild));
+  qb.and_where(Expr::col(UtcDay).gt(to - ROLE_DAYS));
+  qb.group_by_col(UserId);
+  qb.and_having(Expr::expr(Func::sum(Expr::col(Count))).gte(messages));
+  Ok(fetch_all!(&qb, (i64,))?.into_iter().map(|u| u.0).collect())
+}
+
+/// Give the auto role to active members, and take it from the ones that stopped being active
+pub async fn sync_roles(c: &Context) -> R {
+  let guilds = {
+    use Guilds::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.columns([GuildId, RoleId, RoleMessages]);
+    qb.and_where(Expr::col(RoleId).is_not_null());
+    fetch_all!(&qb, (i64, i64, i32))?
+  };
+  for (guild, role, messages) in guilds {
+    let active = active_members(guild, messages).await?;
+    let (guild, role) = (
+      GuildId(guild as u64),
+      poise::serenity_prelude::RoleId(role as u64),
+    );
+    let (mut added, mut removed) = (0, 0);
+    let mut members = guild.members_iter(c).boxed();
+    while let Some(member) = members.next().await {
+      let Ok(mut member) = member else {
+        continue;
+      };
+      let has = member.roles.contains(&role);
+      let should = active.contains(&(member.user.id.0 as i64));
+      let res = if should && !has {
+        member.add_role(c, role).await.map(|_| added += 1)
+      } else if !should && has {
+        member.remove_role(c, role).await.map(|_| removed += 1)
+      } else {
+        continue;
+      };
+      if let Err(err) = res {
+        log::warn!(
+          "Failed to update activity role of {} in {guild}: {err}",
+          member.user.id
+        );
+      }
+    }
+    log::info!("Synced activity role of {guild}, added {added}, removed {removed}");
+  }
+  Ok(())
+}
+
+cmd_group!(activity, "top", "enable", "disable", "role");
+
+#[poise::command(prefix_command, slash_command, guild_only)]
+pub async fn top(ctx: Ctx<'_>, period: Option<Period>, channel: Option<GuildChannel>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  if !check_enabled(guild).await? {
+    ctx
+      .reply("Activity isn't tracked in this guild, enable it with `/activity enable`")
+      .await?;
+    return Ok(());
+  }
+  let period = period.unwrap_or(Period::Month);
+  let span = period.span();
+  let qb = {
+    use Messages::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(UserId);
+    qb.expr_as(Func::sum(Expr::col(Count)), Alias::new("total"));
+    qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+    qb.and_where(Expr::col(UtcDay).between(span.from, span.to));
+    if let Some(channel) = &channel {
+      qb.and_where(Expr::col(ChannelId).eq(channel.id.0 as i64));
+    }
+    qb.group_by_col(UserId);
+    qb.order_by(Alias::new("total"), Order::Desc);
+    qb.order_by(UserId, Order::Asc);
+    qb
+  };
+  let mut input = format!(
+    "Most active members over the last {}",
+    period.to_string().to_lowercase()
+  );
+  if let Some(channel) = &channel {
+    input += &format!(" in <#{}>", channel.id);
+  }
+  paginate(ctx, count_rows(qb.clone()), SIZE, |page| {
+    let mut pb = qb.clone();
+    pb.limit(SIZE);
+    pb.offset(page * SIZE);
+    let input = input.clone();
+    async move {
+      let data = fetch_all!(&pb, (i64, i64))?;
+      let max = data
+        .iter()
+        .map(|d| d.1.to_string().len())
+        .max()
+        .unwrap_or(5);
+      let mut output = String::new();
+      for (user, total) in data {
+        output += &format!("`{total: >max$}` <@{user}>\n");
+      }
+      if output.is_empty() {
+        output = "No messages were counted yet\n".into();
+      }
+      Ok(format!("{input}:\n{output}"))
+    }
+  })
+  .await
+}
+
+fn invalidate() {
+  let mut cache = ENABLED.write().unwrap_or_else(|e| e.into_inner());
+  GENERATION.fetch_add(1, Ordering::SeqCst);
+  *cache = None;
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn enable(ctx: Ctx<'_>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  use Guilds::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId]);
+  qb.values([(guild.0 as i64).into()])?;
+  qb.on_conflict(OnConflict::column(GuildId).do_nothing().to_owned());
+  execute!(&qb)?;
+  invalidate();
+  ctx
+    .reply("Message counts of this guild will now be tracked, message contents are never stored")
+    .await?;
+  Ok(())
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn disable(ctx: Ctx<'_>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  use Guilds::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+  // Removing the guild cascades to removing all of its counts
+  execute!(&qb)?;
+  invalidate();
+  // Buffered counts would fail the foreign key on the next flush
+  if let Some(counts) = COUNTS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+    counts.retain(|k, _| k.0 != guild.0 as i64);
+  }
+  ctx
+    .reply("Activity is no longer tracked, and the counted messages were removed")
+    .await?;
+  Ok(())
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn role(ctx: Ctx<'_>, role: Option<Role>, messages: Option<u32>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  if !check_enabled(guild).await? {
+    ctx
+      .reply("Activity isn't tracked in this guild, enable it with `/activity enable`")
+      .await?;
+    return Ok(());
+  }
+  if let Some(role) = &role {
+    if role.id.0 == guild.0 || role.managed {
+      ctx.reply("This role can't be granted to members").await?;
+      return Ok(());
+    }
+    // Bots can only manage roles below their own highest role
+    let bot = guild.member(ctx, ctx.framework().bot_id).await?;
+    let position = bot.highest_role_info(ctx).map(|(_, p)| p).unwrap_or(0);
+    if !bot.permissions(ctx)?.contains(Permissions::MANAGE_ROLES) || role.position >= position {
+      ctx
+        .reply(format!(
+          "I'm not able to manage {}, make sure my role is above it",
+          role.name
+        ))
+        .await?;
+      return Ok(());
+    }
+  }
+  let messages = messages.unwrap_or(50).max(1) as i32;
+  {
+    use Guilds::*;
+    let mut qb = Query::update();
+    qb.table(Table);
+    qb.value(RoleId, role.as_ref().map(|r| r.id.0 as i64));
+    qb.value(RoleMessages, messages);
+    qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+    execute!(&qb)?;
+  }
+  match role {
+    Some(role) => {
+      ctx
+        .reply(format!(
+          "Members with {messages} or more messages in the last {ROLE_DAYS} days will get <@&{}>",
+          role.id
+        ))
+        .await?
+    }
+    None => ctx.reply("The activity role was removed").await?,
+  };
+  Ok(())
+}
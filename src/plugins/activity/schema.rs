@@ -0,0 +1,25 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use sea_query::Iden;
+
+#[derive(Iden)]
+#[iden(rename = "activity_guilds")]
+pub enum Guilds {
+  Table,
+  GuildId,
+  RoleId,
+  RoleMessages,
+}
+
+#[derive(Iden)]
+#[iden(rename = "activity_messages")]
+pub enum Messages {
+  Table,
+  GuildId,
+  ChannelId,
+  UserId,
+  UtcDay,
+  Count,
+}
@@ -6,11 +6,12 @@ use crate::{
   core::*,
   modules::poise::{Ctx, Poise},
   plugins::{
-    beatleader::update_scores, gwaaa::get_mc_users, steam::{filter_roles, get_roles, minor_update}
+    beatleader::update_scores,
+    steam::{minor_update, sync::sync_guild},
   },
 };
-use futures::StreamExt;
-use poise::serenity_prelude::{Role, RoleId};
+use itertools::Itertools;
+use unicode_truncate::UnicodeTruncateStr;
 
 // Util module for maintenance commands
 pub struct Atakku;
@@ -54,37 +55,30 @@ async fn update_beatleader(ctx: Ctx<'_>) -> R {
   Ok(())
 }
 
-const MCROLE: RoleId = RoleId(1341770285082214462);
-use itertools::Itertools;
-
 #[poise::command(prefix_command, hide_in_help, owners_only)]
-async fn update_roles(ctx: Ctx<'_>) -> R {
+async fn update_roles(ctx: Ctx<'_>, dry_run: Option<bool>) -> R {
+  let dry_run = dry_run.unwrap_or(false);
   let m = ctx.reply("Updating steam roles...").await?;
 
+  let mut report = String::new();
   if let Some(g) = ctx.guild_id() {
-    let mut members = g.members_iter(&ctx).boxed();
-    let mcusers = get_mc_users().await?;
-
-    while let Some(member_result) = members.next().await {
-      match member_result {
-        Ok(mut m) => {
-          if !m.user.bot {
-            let ogroles = m.roles(ctx).unwrap_or_default();
-            let mut roles = filter_roles(&ogroles, get_roles(&m).await?);
-            if mcusers.contains(&m.user.id) && !ogroles.iter().map(|a|a.id).contains(&MCROLE) {
-              roles.push(MCROLE);
-            }
-            if !roles.is_empty() {
-              m.add_roles(ctx, roles.as_slice()).await?;
-            }
-          }
-        }
-        _ => {}
-      }
+    let diffs = sync_guild(ctx.serenity_context(), g, dry_run).await?;
+    for diff in &diffs {
+      let add = diff.add.iter().map(|r| format!("+<@&{r}>")).join(" ");
+      let remove = diff.remove.iter().map(|r| format!("-<@&{r}>")).join(" ");
+      report += &format!("<@{}> {add} {remove}\n", diff.user);
     }
+    report = format!("{} members to update\n{report}", diffs.len());
   }
 
-  m.edit(ctx, |m| m.content("Done updating steam roles!"))
-    .await?;
+  let content = match dry_run {
+    true => format!("Dry run of steam roles:\n{report}"),
+    false => format!("Done updating steam roles!\n{report}"),
+  };
+  m.edit(ctx, |m| {
+    m.content(content.unicode_truncate(2000).0)
+      .allowed_mentions(|a| a.empty_parse())
+  })
+  .await?;
   Ok(())
 }
@@ -4,17 +4,20 @@
 
 use crate::{
   core::*,
-  modules::{cron::Cron, poise::Poise, reqwest::req, sqlx::Postgres},
+  modules::{
+    cron::Cron,
+    poise::{paginate, Poise},
+    reqwest::{req, RateLimit},
+    sqlx::{count_rows, Postgres},
+  },
   plugins::{
-    discord::schema::Users,
+    discord::{display_name, schema::Users},
     neko::{
       query::all_steam_connections,
       schema::{UsersDiscord, UsersSteam},
     },
-    steam::{pagination_buttons, query::ratelimit},
   },
 };
-use poise::serenity_prelude::{CollectComponentInteraction, InteractionResponseType};
 use serde::Deserialize;
 use sqlx::FromRow;
 use tokio_cron_scheduler::Job;
@@ -36,22 +39,11 @@ impl Module for BeatLeader {
 use crate::modules::poise::Ctx;
 
 const SIZE: u64 = 15;
-const PAGES: u64 = 100; //todo
 
 #[poise::command(slash_command)]
 pub async fn beetleader(ctx: Ctx<'_>) -> R {
   let input = "BeetLeader top:";
 
-  let mut msg = ctx
-    .send(|b| {
-      b.content(input).components(|b| {
-        b.create_action_row(|b| pagination_buttons(b, 0, 0, true, "pg_disp".into()))
-      })
-    })
-    .await?
-    .into_message()
-    .await?;
-
   let qb = {
     let mut qb = Query::select();
     qb.from(UsersSteam::Table);
@@ -61,7 +53,7 @@ pub async fn beetleader(ctx: Ctx<'_>) -> R {
     qb.and_where(ex_col!(Users, Id).equals(col!(UsersDiscord, DiscordId)));
     qb.and_where(ex_col!(UsersSteam, NekoId).equals(col!(UsersDiscord, NekoId)));
     qb.and_where(ex_col!(UsersSteam, SteamId).equals(col!(BeetleaderLB, SteamId)));
-    qb.column(col!(Users, Name));
+    qb.expr_as(display_name(None), Alias::new("name"));
     qb.column(col!(BeetleaderLB, Pp));
     {
       qb.expr_window_as(
@@ -76,7 +68,7 @@ pub async fn beetleader(ctx: Ctx<'_>) -> R {
     qb
   };
 
-  let get_page = async move |page: u64| -> Res<String> {
+  paginate(ctx, count_rows(qb.clone()), SIZE, |page| {
     let mut pb = qb.clone();
     if page == 0 {
       pb.limit(SIZE + 1);
@@ -84,83 +76,22 @@ pub async fn beetleader(ctx: Ctx<'_>) -> R {
       pb.limit(SIZE + 2);
       pb.offset(page * SIZE - 1);
     }
-    let data = fetch_all!(&pb, QueryOutput)?;
-    let mut output = String::new();
-    for (i, d) in data.iter().enumerate() {
-      if i == SIZE as usize && page == 0 || i == SIZE as usize + 1 && page != 0 {
-        output += "-------------------\n"
-      }
-      output += &format!("{} | {} | {}\n", d.row_num, d.pp, d.name);
-      if i == 0 && page != 0 {
-        output += "-------------------\n"
+    async move {
+      let data = fetch_all!(&pb, QueryOutput)?;
+      let mut output = String::new();
+      for (i, d) in data.iter().enumerate() {
+        if i == SIZE as usize && page == 0 || i == SIZE as usize + 1 && page != 0 {
+          output += "-------------------\n"
+        }
+        output += &format!("{} | {} | {}\n", d.row_num, d.pp, d.name);
+        if i == 0 && page != 0 {
+          output += "-------------------\n"
+        }
       }
+      Ok(format!("{input}\n```\n# | pp | name \n{output}```\nTo add your steam to this list, head over to <https://link.neko.rs>\nThis bot is still in early development, so bear with the bad design, feedback is appreciated\nDebug locale: {}", ctx.locale().unwrap_or("none")))
     }
-    Ok(output)
-  };
-
-  let mut page = 0;
-  let firstpage = get_page(page).await?;
-
-  msg
-    .edit(ctx, |b| {
-      b.content(format!("{input}\n```\n# | pp | name \n{firstpage}```\nTo add your steam to this list, head over to <https://link.neko.rs>\nThis bot is still in early development, so bear with the bad design, feedback is appreciated\nDebug locale: {}", ctx.locale().unwrap_or("none")))
-        .components(|b| {
-          b.create_action_row(|b| pagination_buttons(b, page, PAGES, false, "".into()))
-        })
-    })
-    .await?;
-
-  let mut id = msg.id.0;
-
-  while let Some(press) = CollectComponentInteraction::new(ctx)
-    .message_id(msg.id)
-    .timeout(std::time::Duration::from_secs(300))
-    .await
-  {
-    match press.data.custom_id.as_str() {
-      "pg_prev" => page -= 1,
-      "pg_next" => page += 1,
-      _ => {}
-    }
-
-    press
-      .create_interaction_response(ctx, |f| {
-        f.kind(InteractionResponseType::UpdateMessage)
-          .interaction_response_data(|b| {
-            b.components(|b| {
-              b.create_action_row(|b| {
-                pagination_buttons(b, page, PAGES, true, press.data.custom_id.clone())
-              })
-            })
-          })
-      })
-      .await?;
-
-    let pageee = get_page(page).await.unwrap();
-
-    let mut msg = press.get_interaction_response(ctx).await?;
-    msg
-      .edit(ctx, |b| {
-        b.content(format!("{input}\n```\n# | pp | name \n{pageee}```\nTo add your steam to this list, head over to <https://link.neko.rs>\nThis bot is still in early development, so bear with the bad design, feedback is appreciated\nDebug locale: {}", ctx.locale().unwrap_or("none")))
-          .components(|b| {
-            b.create_action_row(|b| {
-              pagination_buttons(b, page, PAGES, false, press.data.custom_id.clone())
-            })
-          })
-      })
-      .await?;
-
-    id = msg.id.0;
-  }
-  ctx
-    .http()
-    .get_message(msg.channel_id.0, id)
-    .await?
-    .edit(ctx, |m| {
-      m.components(|b| b.create_action_row(|b| pagination_buttons(b, page, PAGES, true, "".into())))
-    })
-    .await?;
-  Ok(())
+  })
+  .await
 }
 
 #[derive(FromRow)]
@@ -170,6 +101,26 @@ pub struct QueryOutput {
   pub pp: f32,
 }
 
+/// Best pp out of the given accounts, along with its position in the top
+pub async fn beatleader_rank(steam_ids: Vec<i64>) -> Res<Option<(i64, f32, i64)>> {
+  use BeetleaderLB::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.columns([SteamId, Pp]);
+  qb.and_where(Expr::col(SteamId).is_in(steam_ids));
+  qb.order_by(Pp, Order::Desc);
+  qb.limit(1);
+  let Some((id, pp)) = fetch_optional!(&qb, (i64, f32))? else {
+    return Ok(None);
+  };
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.expr(Func::count(Expr::col(Asterisk)));
+  qb.and_where(Expr::col(Pp).gt(pp));
+  let (above,) = fetch_one!(&qb, (i64,))?;
+  Ok(Some((id, pp, above + 1)))
+}
+
 pub async fn update_scores() -> R {
   let c = all_steam_connections().await?;
   let mut push: Vec<(i64, f32)> = vec![];
@@ -202,7 +153,7 @@ pub async fn update_scores() -> R {
   Ok(())
 }
 
-use sea_query::{Alias, Func, Iden, OnConflict, Order, Query, WindowStatement};
+use sea_query::{Alias, Asterisk, Expr, Func, Iden, OnConflict, Order, Query, WindowStatement};
 
 #[derive(Iden)]
 #[iden(rename = "beetleader_lb")]
@@ -212,13 +163,15 @@ pub enum BeetleaderLB {
   Pp,
 }
 
+static BUDGET: RateLimit = RateLimit::new(std::time::Duration::from_millis(1600));
+
 pub async fn get_scores(id: i64, t: i64) -> Res<Vec<PlayerScoresData>> {
-  let mut page = futures::join!(get_scores_paginated(id, t, 1), ratelimit()).0?;
+  let mut page = futures::join!(get_scores_paginated(id, t, 1), BUDGET.wait()).0?;
   let mut data: Vec<PlayerScoresData> = vec![];
   data.append(&mut page.data);
   if page.metadata.total > 100 {
     for i in 2..=(page.metadata.total as f64 / 100_f64).ceil() as u64 {
-      let mut page = futures::join!(get_scores_paginated(id, t, i), ratelimit()).0?;
+      let mut page = futures::join!(get_scores_paginated(id, t, i), BUDGET.wait()).0?;
       data.append(&mut page.data);
     }
   }
@@ -2,33 +2,73 @@
 //
 // This project is dual licensed under MIT and Apache.
 
+use self::{
+  settings::{scrape_settings, ScrapeSettings},
+  stats::{count_event, record_changes, record_event, server, stats_json, MemberEvent},
+  voice::{reconcile_voice, update_voice, voice},
+  whitelist::{
+    check_guild_whitelist, init_whitelist, leave_expired, remove_request, request_access, whitelist,
+  },
+};
 use crate::{
   core::*,
   modules::{
-    poise::{EventHandler, Poise},
+    axum::Axum,
+    cron::Cron,
+    poise::{try_sctx, EventHandler, Poise},
     sqlx::Postgres,
   },
   plugins::discord::schema::*,
 };
-use futures::StreamExt;
+use axum::routing::get;
+use chrono::Utc;
 use poise::{
-  serenity_prelude::{Context, GatewayIntents, GuildId, Member, Role, RoleId, User, UserId},
+  serenity_prelude::{
+    ChunkGuildFilter, Context, GatewayIntents, GuildId, GuildMembersChunkEvent, Member, Role,
+    RoleId, User, UserId,
+  },
   Event,
 };
-use sea_query::{Expr, OnConflict, Query};
+use sea_query::{Expr, Func, OnConflict, Query, SimpleExpr};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+use tokio_cron_scheduler::Job;
 
 pub mod schema;
+pub mod settings;
+pub mod stats;
+pub mod voice;
+pub mod whitelist;
 
 /// Discord scraper module, populates the database with user data (users, guilds, members)
 pub struct Discord;
 
 impl Module for Discord {
   async fn init(&mut self, fw: &mut Framework) -> R {
+    init_whitelist()?;
     fw.req_module::<Postgres>().await?;
     let poise = fw.req_module::<Poise>().await?;
+    poise.commands.push(scrape());
+    poise.commands.push(whitelist());
+    poise.commands.push(server());
+    poise.commands.push(voice());
     poise.event_handlers.push(event_handler());
     poise.intents.insert(GatewayIntents::GUILDS);
     poise.intents.insert(GatewayIntents::GUILD_MEMBERS);
+    poise.intents.insert(GatewayIntents::GUILD_VOICE_STATES);
+    cron!(fw, "0 */10 * * * *", || {
+      if let Some(c) = try_sctx() {
+        if let Err(err) = leave_expired(c).await {
+          log::warn!("Failed to leave guilds that weren't whitelisted: {err}");
+        }
+      }
+    });
+    let axum = fw.req_module::<Axum>().await?;
+    axum
+      .routes
+      .push(|r| Box::pin(async move { Ok(r.route("/api/guilds/:id/stats", get(stats_json))) }));
     Ok(())
   }
 }
@@ -38,27 +78,17 @@ fn event_handler() -> EventHandler {
     Box::pin(async move {
       use Event::*;
       match event {
-        Ready { data_about_bot: _ } => {
-          prune_all_guilds().await?;
-          // Removing guild cascades to removing all guild members
+        Ready { data_about_bot: r } => {
+          // Only guilds that were left while offline, the rest get reconciled on GuildCreate
+          prune_guilds(r.guilds.iter().map(|g| g.id).collect()).await?;
         }
-        GuildCreate {
-          guild: g,
-          is_new: _,
-        } => {
+        GuildCreate { guild: g, is_new } => {
           if check_guild_whitelist(g.id).await? {
             update_guild(c, g.id).await?;
-            let res: Vec<_> = g.id.members_iter(c).collect().await;
-            let members: Vec<_> = res
-              .into_iter()
-              .filter_map(Result::ok)
-              .filter(|m| !m.user.bot)
-              .filter(|m| g.id.0 == 1404602275401568347 || m.roles.contains(&RoleId::from(1232817578037084262)))
-              .collect();
-            let users: Vec<_> = members.clone().into_iter().map(|m| m.user).collect();
-            update_users(users).await?;
-            // No need to prune members, as bot does that on GuildDelete and Ready
-            update_members(members).await?;
+            scrape_guild(c, g.id).await?;
+            reconcile_voice(g).await?;
+          } else if *is_new {
+            request_access(c, g).await?;
           }
         }
         GuildUpdate {
@@ -73,15 +103,22 @@ fn event_handler() -> EventHandler {
           incomplete: g,
           full: _,
         } => {
-          if !g.unavailable && check_guild_whitelist(g.id).await? {
-            remove_guild(g.id).await?;
-            // Removing guild cascades to removing all guild members
+          if !g.unavailable {
+            // Nothing left to whitelist or leave
+            remove_request(g.id).await?;
+            if check_guild_whitelist(g.id).await? {
+              remove_guild(g.id).await?;
+              // Removing guild cascades to removing all guild members
+            }
           }
         }
         GuildMemberAddition { new_member: m } => {
           if !m.user.bot && check_guild_whitelist(m.guild_id).await? {
-            update_users(vec![m.user.clone()]).await?;
-            update_members(vec![m.clone()]).await?;
+            count_event(m.guild_id, MemberEvent::Join).await?;
+            if scrape_settings(m.guild_id).await?.allows(m) {
+              record_event(m.guild_id, m.user.id, MemberEvent::Join, None).await?;
+            }
+            scrape_member(m).await?;
           }
         }
         GuildMemberUpdate {
@@ -89,23 +126,47 @@ fn event_handler() -> EventHandler {
           new: m,
         } => {
           if !m.user.bot && check_guild_whitelist(m.guild_id).await? {
-            update_users(vec![m.user.clone()]).await?;
-            if m.guild_id == 1404602275401568347 || m.roles.contains(&RoleId(1232817578037084262)) {
-              update_members(vec![m.clone()]).await?;
-            } else {
-              remove_member(m.guild_id, m.user.id).await?;
-            }
+            scrape_member(m).await?;
           }
         }
         GuildMemberRemoval {
           guild_id: g,
           user: u,
-          member_data_if_available: _,
+          member_data_if_available: m,
         } => {
           if !u.bot && check_guild_whitelist(*g).await? {
-            remove_member(*g, u.id).await?;
+            count_event(*g, MemberEvent::Leave).await?;
+            let stored = remove_member(*g, u.id).await?;
+            // Without the cached member, only members that were scraped are known to be allowed
+            let allowed = match m {
+              Some(m) => scrape_settings(*g).await?.allows(m),
+              None => stored,
+            };
+            if allowed {
+              record_event(*g, u.id, MemberEvent::Leave, None).await?;
+            }
+          }
+        }
+        GuildRoleCreate { new: r } | GuildRoleUpdate { new: r, .. } => {
+          if check_guild_whitelist(r.guild_id).await? {
+            update_roles(vec![r.clone()]).await?;
+          }
+        }
+        GuildRoleDelete {
+          guild_id: g,
+          removed_role_id: r,
+          removed_role_data_if_available: _,
+        } => {
+          if check_guild_whitelist(*g).await? {
+            remove_role(*g, *r).await?;
           }
         }
+        GuildMembersChunk { chunk } => {
+          handle_chunk(chunk).await?;
+        }
+        VoiceStateUpdate { old: _, new } => {
+          update_voice(new).await?;
+        }
         _ => {}
       }
       Ok(())
@@ -113,13 +174,114 @@ fn event_handler() -> EventHandler {
   }
 }
 
-async fn check_guild_whitelist(id: GuildId) -> Res<bool> {
-  use crate::plugins::neko::schema::WhitelistDiscord::*;
-  let mut qb = Query::select();
-  qb.from(Table);
-  qb.column(GuildId);
-  qb.and_where(Expr::col((Table, GuildId)).eq(id.0 as i64));
-  Ok(fetch_optional!(&qb, (i64,))?.is_some())
+// Member syncs in progress, members stored with an older generation are removed once all chunks arrive
+static SYNCS: Mutex<Option<HashMap<GuildId, MemberSync>>> = Mutex::new(None);
+
+struct MemberSync {
+  generation: i64,
+  settings: Arc<ScrapeSettings>,
+  received: u32,
+}
+
+fn syncs<T>(f: impl FnOnce(&mut HashMap<GuildId, MemberSync>) -> T) -> T {
+  let mut syncs = SYNCS.lock().unwrap_or_else(|e| e.into_inner());
+  f(syncs.get_or_insert_with(HashMap::new))
+}
+
+/// Request every member of the guild over the gateway, the stored ones are reconciled as chunks arrive
+pub async fn scrape_guild(c: &Context, guild: GuildId) -> R {
+  let settings = scrape_settings(guild).await?;
+  let generation = Utc::now().timestamp_millis();
+  syncs(|s| {
+    s.insert(
+      guild,
+      MemberSync {
+        generation,
+        settings,
+        received: 0,
+      },
+    )
+  });
+  log::trace!("Requesting members of {guild}, generation {generation}");
+  c.shard.chunk_guild(
+    guild,
+    None,
+    ChunkGuildFilter::None,
+    Some(generation.to_string()),
+  );
+  Ok(())
+}
+
+async fn handle_chunk(chunk: &GuildMembersChunkEvent) -> R {
+  let guild = chunk.guild_id;
+  let Some(generation) = chunk.nonce.as_ref().and_then(|n| n.parse::<i64>().ok()) else {
+    return Ok(());
+  };
+  // Stale chunks of a sync that was superseded by a newer one are ignored
+  let Some(members) = syncs(|s| {
+    let sync = s.get(&guild).filter(|sync| sync.generation == generation)?;
+    let members: Vec<_> = (chunk.members.values())
+      .filter(|m| !m.user.bot && sync.settings.allows(m))
+      .cloned()
+      .collect();
+    Some(members)
+  }) else {
+    return Ok(());
+  };
+  let users: Vec<_> = members.iter().map(|m| m.user.clone()).collect();
+  update_users(users).await?;
+  update_members(members, Some(generation)).await?;
+  // Only counted once stored, so that the sweep can't race earlier chunks
+  let done = syncs(|s| {
+    let sync = s
+      .get_mut(&guild)
+      .filter(|sync| sync.generation == generation)?;
+    sync.received += 1;
+    let done = sync.received >= chunk.chunk_count;
+    if done {
+      s.remove(&guild);
+    }
+    Some(done)
+  });
+  if done == Some(true) {
+    let removed = sweep_members(guild, generation).await?;
+    log::info!("Synced members of {guild}, removed {removed}");
+  }
+  Ok(())
+}
+
+/// Store or remove a single member, depending on the guild's scrape policy
+pub async fn scrape_member(m: &Member) -> R {
+  update_users(vec![m.user.clone()]).await?;
+  if scrape_settings(m.guild_id).await?.allows(m) {
+    record_changes(m).await?;
+    // Joins during a sync must not be swept by it
+    let generation = syncs(|s| s.get(&m.guild_id).map(|s| s.generation));
+    update_members(vec![m.clone()], generation).await?;
+  } else {
+    remove_member(m.guild_id, m.user.id).await?;
+  }
+  Ok(())
+}
+
+/// Name a user is shown with, their nick in the guild, then their username.
+/// Global display names aren't exposed by serenity 0.11, so `discord_users.nick` stays empty
+/// Expects `discord_users` to be in the query
+pub fn display_name(guild: Option<i64>) -> SimpleExpr {
+  let mut names: Vec<SimpleExpr> = vec![];
+  if let Some(guild) = guild {
+    let mut sq = Query::select();
+    sq.from(Members::Table);
+    sq.column(Members::Nick);
+    sq.and_where(Expr::col((Members::Table, Members::GuildId)).eq(guild));
+    sq.and_where(ex_col!(Members, UserId).equals(col!(Users, Id)));
+    names.push(SimpleExpr::SubQuery(
+      None,
+      Box::new(sq.into_sub_query_statement()),
+    ));
+  }
+  names.push(ex_col!(Users, Name).into());
+  Func::coalesce(names).into()
 }
 
 async fn update_guild(ctx: &Context, id: GuildId) -> R {
@@ -137,6 +299,63 @@ async fn update_guild(ctx: &Context, id: GuildId) -> R {
   );
   qb.values([info.id.0.into(), info.name.into(), info.icon.into()])?;
   execute!(&qb)?;
+  let roles: Vec<_> = id.roles(ctx).await?.into_values().collect();
+  prune_roles(id, roles.iter().map(|r| r.id).collect()).await?;
+  update_roles(roles).await?;
+  Ok(())
+}
+
+async fn update_roles(roles: Vec<Role>) -> R {
+  use Roles::*;
+  if roles.is_empty() {
+    return Ok(());
+  }
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([Id, GuildId, Name, Color, Position]);
+  qb.on_conflict(
+    OnConflict::column(Id)
+      .update_columns([Name, Color, Position])
+      .to_owned(),
+  );
+  for r in roles {
+    qb.values([
+      r.id.0.into(),
+      r.guild_id.0.into(),
+      r.name.into(),
+      (r.colour.0 as i32).into(),
+      r.position.into(),
+    ])?;
+  }
+  execute!(&qb)?;
+  Ok(())
+}
+
+// Remove roles of the guild that no longer exist
+async fn prune_roles(g: GuildId, existing: Vec<RoleId>) -> R {
+  use Roles::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.cond_where(Expr::col(GuildId).eq(g.0));
+  qb.cond_where(Expr::col(Id).is_not_in(existing.into_iter().map(|r| r.0)));
+  execute!(&qb)?;
+  Ok(())
+}
+
+async fn remove_role(g: GuildId, role: RoleId) -> R {
+  {
+    use Roles::*;
+    let mut qb = Query::delete();
+    qb.from_table(Table);
+    qb.cond_where(Expr::col(Id).eq(role.0));
+    execute!(&qb)?;
+  }
+  use MemberRoles::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.cond_where(Expr::col(GuildId).eq(g.0));
+  qb.cond_where(Expr::col(RoleId).eq(role.0));
+  execute!(&qb)?;
   Ok(())
 }
 
@@ -150,11 +369,12 @@ async fn remove_guild(id: GuildId) -> R {
   Ok(())
 }
 
-async fn prune_all_guilds() -> R {
+async fn prune_guilds(joined: Vec<GuildId>) -> R {
   use Guilds::*;
-  log::trace!("Pruning all guilds");
+  log::trace!("Pruning guilds other than the {} joined ones", joined.len());
   let mut qb = Query::delete();
   qb.from_table(Table);
+  qb.cond_where(Expr::col(Id).is_not_in(joined.into_iter().map(|g| g.0)));
   execute!(&qb)?;
   Ok(())
 }
@@ -188,39 +408,188 @@ async fn update_users(users: Vec<User>) -> R {
   Ok(())
 }
 
-async fn update_members(members: Vec<Member>) -> R {
+// Members are marked with the generation of the sync that saw them, if any
+async fn update_members(members: Vec<Member>, generation: Option<i64>) -> R {
   use Members::*;
   log::trace!("Updating {} members", members.len());
   for chunk in members.chunks(CHUNK_SIZE) {
+    let mut columns = vec![GuildId, UserId, Nick, Avatar];
+    let mut updated = vec![Nick, Avatar];
+    if generation.is_some() {
+      columns.push(SyncedGen);
+      updated.push(SyncedGen);
+    }
     let mut qb = Query::insert();
     qb.into_table(Table);
-    qb.columns([GuildId, UserId, Nick, Avatar]);
+    qb.columns(columns);
     qb.on_conflict(
       OnConflict::columns([GuildId, UserId])
-        .update_columns([Nick, Avatar])
+        .update_columns(updated)
         .to_owned(),
     );
-    for row in chunk.into_iter().map(|m| {
-      [
+    for m in chunk {
+      let mut row: Vec<SimpleExpr> = vec![
         m.guild_id.0.into(),
         m.user.id.0.into(),
         m.nick.clone().into(),
         m.avatar.clone().into(),
-      ]
-    }) {
+      ];
+      if let Some(generation) = generation {
+        row.push(generation.into());
+      }
       qb.values(row)?;
     }
     execute!(&qb)?;
+    update_member_roles(chunk).await?;
+  }
+  Ok(())
+}
+
+// Replaces the stored roles of the members with their current ones
+async fn update_member_roles(members: &[Member]) -> R {
+  use MemberRoles::*;
+  let mut guilds: HashMap<_, Vec<u64>> = HashMap::new();
+  for m in members {
+    guilds.entry(m.guild_id).or_default().push(m.user.id.0);
+  }
+  for (g, users) in guilds {
+    let mut qb = Query::delete();
+    qb.from_table(Table);
+    qb.cond_where(Expr::col(GuildId).eq(g.0));
+    qb.cond_where(Expr::col(UserId).is_in(users));
+    execute!(&qb)?;
+  }
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, UserId, RoleId]);
+  let mut empty = true;
+  for m in members {
+    for r in &m.roles {
+      qb.values([m.guild_id.0.into(), m.user.id.0.into(), r.0.into()])?;
+      empty = false;
+    }
+  }
+  if !empty {
+    execute!(&qb)?;
   }
   Ok(())
 }
 
-async fn remove_member(g: GuildId, u: UserId) -> R {
+// Remove members that weren't seen by the given sync, returning how many were removed
+async fn sweep_members(g: GuildId, generation: i64) -> Res<u64> {
+  use Members::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.cond_where(Expr::col(GuildId).eq(g.0));
+  qb.cond_where(Expr::col(SyncedGen).ne(generation));
+  Ok(execute!(&qb)?.rows_affected())
+}
+
+/// Remove a stored member, returning whether they were stored
+async fn remove_member(g: GuildId, u: UserId) -> Res<bool> {
   use Members::*;
   let mut qb = Query::delete();
   qb.from_table(Table);
   qb.cond_where(Expr::col(GuildId).eq(g.0));
   qb.cond_where(Expr::col(UserId).eq(u.0));
-  execute!(&qb)?;
-  Ok(())
+  Ok(execute!(&qb)?.rows_affected() > 0)
+}
+
+cmd_group!(
+  scrape,
+  "scrape::policy",
+  "scrape::role",
+  "scrape::optin",
+  "scrape::show"
+);
+
+mod scrape {
+  use crate::{
+    core::R,
+    modules::poise::Ctx,
+    plugins::discord::{
+      scrape_guild, scrape_member,
+      settings::{scrape_settings, set_optin, set_policy, set_role, ScrapePolicy},
+    },
+  };
+  use poise::serenity_prelude::Role;
+
+  #[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+  )]
+  pub async fn policy(ctx: Ctx<'_>, policy: ScrapePolicy) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.defer().await?;
+    set_policy(guild, policy).await?;
+    scrape_guild(ctx.serenity_context(), guild).await?;
+    ctx
+      .reply(format!("Now scraping {}", policy.describe()))
+      .await?;
+    Ok(())
+  }
+
+  #[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+  )]
+  pub async fn role(ctx: Ctx<'_>, role: Role, enabled: bool) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.defer().await?;
+    if !set_role(guild, role.id, enabled).await? {
+      ctx.reply("Nothing changed").await?;
+      return Ok(());
+    }
+    let settings = scrape_settings(guild).await?;
+    if settings.policy == ScrapePolicy::Roles {
+      scrape_guild(ctx.serenity_context(), guild).await?;
+    }
+    let action = if enabled { "now" } else { "no longer" };
+    ctx
+      .reply(format!("Members with <@&{}> are {action} scraped", role.id))
+      .await?;
+    Ok(())
+  }
+
+  #[poise::command(prefix_command, slash_command, guild_only, ephemeral)]
+  pub async fn optin(ctx: Ctx<'_>, enabled: bool) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    set_optin(guild, ctx.author().id, enabled).await?;
+    let member = guild.member(ctx, ctx.author().id).await?;
+    scrape_member(&member).await?;
+    let action = if enabled {
+      "opted in to"
+    } else {
+      "opted out of"
+    };
+    ctx
+      .reply(format!("You {action} being scraped in this guild"))
+      .await?;
+    Ok(())
+  }
+
+  #[poise::command(prefix_command, slash_command, guild_only)]
+  pub async fn show(ctx: Ctx<'_>) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    let settings = scrape_settings(guild).await?;
+    let mut output = format!("Scraping {}", settings.policy.describe());
+    if !settings.roles.is_empty() {
+      let roles: Vec<_> = settings.roles.iter().map(|r| format!("<@&{r}>")).collect();
+      output += &format!("\nScraped roles: {}", roles.join(", "));
+    }
+    if settings.policy == ScrapePolicy::OptIn {
+      output += &format!("\n{} members opted in", settings.optin.len());
+    }
+    ctx
+      .send(|b| {
+        b.embed(|e| e.title("Scrape policy").description(output))
+          .allowed_mentions(|a| a.empty_parse())
+      })
+      .await?;
+    Ok(())
+  }
 }
@@ -31,4 +31,92 @@ pub enum Members {
   UserId,
   Nick,
   Avatar,
+  // Generation of the last member sync that saw the member
+  SyncedGen,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_guild_settings")]
+pub enum GuildSettings {
+  Table,
+  GuildId,
+  Policy,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_scrape_roles")]
+pub enum ScrapeRoles {
+  Table,
+  GuildId,
+  RoleId,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_scrape_optin")]
+pub enum ScrapeOptin {
+  Table,
+  GuildId,
+  UserId,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_whitelist_requests")]
+pub enum WhitelistRequests {
+  Table,
+  GuildId,
+  RequestedAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_roles")]
+pub enum Roles {
+  Table,
+  Id,
+  GuildId,
+  Name,
+  Color,
+  Position,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_member_roles")]
+pub enum MemberRoles {
+  Table,
+  GuildId,
+  UserId,
+  RoleId,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_member_events")]
+pub enum MemberEvents {
+  Table,
+  Id,
+  GuildId,
+  UserId,
+  Kind,
+  Detail,
+  CreatedAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_member_counts")]
+pub enum MemberCounts {
+  Table,
+  GuildId,
+  UtcDay,
+  Joins,
+  Leaves,
+}
+
+#[derive(Iden)]
+#[iden(rename = "discord_voice_sessions")]
+pub enum VoiceSessions {
+  Table,
+  Id,
+  GuildId,
+  UserId,
+  ChannelId,
+  JoinedAt,
+  LeftAt,
 }
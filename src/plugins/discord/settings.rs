@@ -0,0 +1,197 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::{core::*, plugins::discord::schema::*};
+use poise::{
+  serenity_prelude::{GuildId, Member, RoleId, UserId},
+  ChoiceParameter,
+};
+use sea_query::{Expr, OnConflict, Query};
+use std::{
+  collections::{BTreeMap, HashSet},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, RwLock,
+  },
+};
+
+#[derive(ChoiceParameter, Clone, Copy, PartialEq)]
+pub enum ScrapePolicy {
+  All,
+  Roles,
+  OptIn,
+}
+
+impl ScrapePolicy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ScrapePolicy::All => "all",
+      ScrapePolicy::Roles => "roles",
+      ScrapePolicy::OptIn => "optin",
+    }
+  }
+
+  pub fn parse(policy: &str) -> Option<Self> {
+    match policy {
+      "all" => Some(ScrapePolicy::All),
+      "roles" => Some(ScrapePolicy::Roles),
+      "optin" => Some(ScrapePolicy::OptIn),
+      _ => None,
+    }
+  }
+
+  pub fn describe(&self) -> &'static str {
+    match self {
+      ScrapePolicy::All => "every member",
+      ScrapePolicy::Roles => "members with any of the scraped roles",
+      ScrapePolicy::OptIn => "members that opted in",
+    }
+  }
+}
+
+/// Which members of a guild get stored by the scraper
+pub struct ScrapeSettings {
+  pub policy: ScrapePolicy,
+  pub roles: HashSet<RoleId>,
+  pub optin: HashSet<UserId>,
+}
+
+impl ScrapeSettings {
+  pub fn allows(&self, m: &Member) -> bool {
+    match self.policy {
+      ScrapePolicy::All => true,
+      ScrapePolicy::Roles => m.roles.iter().any(|r| self.roles.contains(r)),
+      ScrapePolicy::OptIn => self.optin.contains(&m.user.id),
+    }
+  }
+}
+
+// Settings of each guild, loaded on first use and dropped whenever they change
+static CACHE: RwLock<BTreeMap<GuildId, Arc<ScrapeSettings>>> = RwLock::new(BTreeMap::new());
+// Bumped on every change, so a load that raced with one doesn't get cached
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub async fn scrape_settings(id: GuildId) -> Res<Arc<ScrapeSettings>> {
+  let cached = (CACHE.read().unwrap_or_else(|e| e.into_inner()))
+    .get(&id)
+    .cloned();
+  if let Some(settings) = cached {
+    return Ok(settings);
+  }
+  let generation = GENERATION.load(Ordering::SeqCst);
+  let settings = Arc::new(load_settings(id).await?);
+  let mut cache = CACHE.write().unwrap_or_else(|e| e.into_inner());
+  if GENERATION.load(Ordering::SeqCst) == generation {
+    cache.insert(id, settings.clone());
+  }
+  Ok(settings)
+}
+
+fn invalidate(id: GuildId) {
+  let mut cache = CACHE.write().unwrap_or_else(|e| e.into_inner());
+  GENERATION.fetch_add(1, Ordering::SeqCst);
+  cache.remove(&id);
+}
+
+async fn load_settings(guild: GuildId) -> Res<ScrapeSettings> {
+  let guild = guild.0 as i64;
+  let policy = {
+    use GuildSettings::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(Policy);
+    qb.and_where(Expr::col(GuildId).eq(guild));
+    fetch_optional!(&qb, (String,))?
+      .and_then(|p| ScrapePolicy::parse(&p.0))
+      .unwrap_or(ScrapePolicy::All)
+  };
+  let roles = {
+    use ScrapeRoles::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(RoleId);
+    qb.and_where(Expr::col(GuildId).eq(guild));
+    fetch_all!(&qb, (i64,))?
+      .into_iter()
+      .map(|r| poise::serenity_prelude::RoleId(r.0 as u64))
+      .collect()
+  };
+  let optin = {
+    use ScrapeOptin::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(UserId);
+    qb.and_where(Expr::col(GuildId).eq(guild));
+    fetch_all!(&qb, (i64,))?
+      .into_iter()
+      .map(|u| poise::serenity_prelude::UserId(u.0 as u64))
+      .collect()
+  };
+  Ok(ScrapeSettings {
+    policy,
+    roles,
+    optin,
+  })
+}
+
+pub async fn set_policy(guild: GuildId, policy: ScrapePolicy) -> R {
+  use GuildSettings::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, Policy]);
+  qb.values([(guild.0 as i64).into(), policy.as_str().into()])?;
+  qb.on_conflict(OnConflict::column(GuildId).update_column(Policy).to_owned());
+  execute!(&qb)?;
+  invalidate(guild);
+  Ok(())
+}
+
+/// Add or remove a scraped role, returning whether anything changed
+pub async fn set_role(guild: GuildId, role: RoleId, enabled: bool) -> Res<bool> {
+  use ScrapeRoles::*;
+  let res = if enabled {
+    let mut qb = Query::insert();
+    qb.into_table(Table);
+    qb.columns([GuildId, RoleId]);
+    qb.values([(guild.0 as i64).into(), (role.0 as i64).into()])?;
+    qb.on_conflict(
+      OnConflict::columns([GuildId, RoleId])
+        .do_nothing()
+        .to_owned(),
+    );
+    execute!(&qb)?
+  } else {
+    let mut qb = Query::delete();
+    qb.from_table(Table);
+    qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+    qb.and_where(Expr::col(RoleId).eq(role.0 as i64));
+    execute!(&qb)?
+  };
+  invalidate(guild);
+  Ok(res.rows_affected() > 0)
+}
+
+pub async fn set_optin(guild: GuildId, user: UserId, enabled: bool) -> R {
+  use ScrapeOptin::*;
+  if enabled {
+    let mut qb = Query::insert();
+    qb.into_table(Table);
+    qb.columns([GuildId, UserId]);
+    qb.values([(guild.0 as i64).into(), (user.0 as i64).into()])?;
+    qb.on_conflict(
+      OnConflict::columns([GuildId, UserId])
+        .do_nothing()
+        .to_owned(),
+    );
+    execute!(&qb)?;
+  } else {
+    let mut qb = Query::delete();
+    qb.from_table(Table);
+    qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+    qb.and_where(Expr::col(UserId).eq(user.0 as i64));
+    execute!(&qb)?;
+  }
+  invalidate(guild);
+  Ok(())
+}
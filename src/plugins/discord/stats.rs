@@ -0,0 +1,343 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::{
+  core::*,
+  modules::{
+    poise::{try_sctx, Ctx},
+    resvg::{Chart, Theme},
+  },
+  plugins::discord::{
+    schema::*,
+    settings::{scrape_settings, ScrapePolicy},
+    whitelist::check_guild_whitelist,
+  },
+};
+use axum::{
+  extract::Path,
+  http::{header, HeaderMap},
+  response::{IntoResponse, Response},
+  Form, Json,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use poise::serenity_prelude::{AttachmentType, GuildId, Member, UserId};
+use reqwest::StatusCode;
+use sea_query::{Alias, Asterisk, Expr, Func, OnConflict, Query};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MemberEvent {
+  Join,
+  Leave,
+  Nick,
+  Roles,
+}
+
+impl MemberEvent {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MemberEvent::Join => "join",
+      MemberEvent::Leave => "leave",
+      MemberEvent::Nick => "nick",
+      MemberEvent::Roles => "roles",
+    }
+  }
+}
+
+pub async fn record_event(g: GuildId, u: UserId, event: MemberEvent, detail: Option<String>) -> R {
+  use MemberEvents::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, UserId, Kind, Detail, CreatedAt]);
+  qb.values([
+    (g.0 as i64).into(),
+    (u.0 as i64).into(),
+    event.as_str().into(),
+    detail.into(),
+    Utc::now().timestamp().into(),
+  ])?;
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Count a join or leave of any member, unlike events these aren't tied to who it was
+pub async fn count_event(g: GuildId, event: MemberEvent) -> R {
+  use MemberCounts::*;
+  let join = match event {
+    MemberEvent::Join => true,
+    MemberEvent::Leave => false,
+    _ => return Ok(()),
+  };
+  let column = || if join { Joins } else { Leaves };
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, UtcDay, column()]);
+  qb.values([
+    (g.0 as i64).into(),
+    (Utc::now().timestamp() / 86400).into(),
+    1.into(),
+  ])?;
+  qb.on_conflict(
+    OnConflict::columns([GuildId, UtcDay])
+      .value(column(), Expr::col((Table, column())).add(1))
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Record nick and role changes of a member, compared to the stored one
+pub async fn record_changes(m: &Member) -> R {
+  let (g, u) = (m.guild_id.0 as i64, m.user.id.0 as i64);
+  let stored = {
+    use Members::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(Nick);
+    qb.and_where(Expr::col(GuildId).eq(g));
+    qb.and_where(Expr::col(UserId).eq(u));
+    fetch_optional!(&qb, (Option<String>,))?
+  };
+  // Not stored yet, so there's nothing to compare to
+  let Some((nick,)) = stored else {
+    return Ok(());
+  };
+  if nick != m.nick {
+    record_event(m.guild_id, m.user.id, MemberEvent::Nick, m.nick.clone()).await?;
+  }
+  let roles: HashSet<i64> = {
+    use MemberRoles::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(RoleId);
+    qb.and_where(Expr::col(GuildId).eq(g));
+    qb.and_where(Expr::col(UserId).eq(u));
+    fetch_all!(&qb, (i64,))?.into_iter().map(|r| r.0).collect()
+  };
+  let current: HashSet<i64> = m.roles.iter().map(|r| r.0 as i64).collect();
+  let added = current.difference(&roles).map(|r| format!("+{r}"));
+  let removed = roles.difference(&current).map(|r| format!("-{r}"));
+  let diff: Vec<_> = added.chain(removed).collect();
+  if !diff.is_empty() {
+    record_event(
+      m.guild_id,
+      m.user.id,
+      MemberEvent::Roles,
+      Some(diff.join(",")),
+    )
+    .await?;
+  }
+  Ok(())
+}
+
+#[derive(Serialize)]
+pub struct DayStats {
+  pub day: String,
+  pub joins: i64,
+  pub leaves: i64,
+  // Only known if the guild is cached
+  pub members: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct GuildStats {
+  pub joins: i64,
+  pub leaves: i64,
+  // Share of members that joined at least that long ago, and didn't leave within that time.
+  // Unknown unless every member is scraped
+  pub retention_7d: Option<f64>,
+  pub retention_30d: Option<f64>,
+  pub days: Vec<DayStats>,
+}
+
+// Joins and leaves of each UTC day since the given one
+async fn daily_events(guild: i64, from: i64) -> Res<HashMap<i64, (i64, i64)>> {
+  use MemberCounts::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.columns([UtcDay, Joins, Leaves]);
+  qb.and_where(Expr::col(GuildId).eq(guild));
+  qb.and_where(Expr::col(UtcDay).gte(from));
+  Ok(
+    fetch_all!(&qb, (i64, i32, i32))?
+      .into_iter()
+      .map(|(day, joins, leaves)| (day, (joins as i64, leaves as i64)))
+      .collect(),
+  )
+}
+
+// Out of the members that joined since `from` and at least `days` ago, how many didn't leave within `days`
+async fn retention(guild: i64, from: i64, days: i64) -> Res<Option<f64>> {
+  use MemberEvents::*;
+  let (j, l) = (Alias::new("j"), Alias::new("l"));
+  let until = Utc::now().timestamp() - days * 86400;
+  let joined = |retained: bool| {
+    let mut qb = Query::select();
+    qb.from_as(Table, j.clone());
+    qb.expr(Func::count(Expr::col(Asterisk)));
+    qb.and_where(Expr::col((j.clone(), GuildId)).eq(guild));
+    qb.and_where(Expr::col((j.clone(), Kind)).eq(MemberEvent::Join.as_str()));
+    qb.and_where(Expr::col((j.clone(), CreatedAt)).gte(from * 86400));
+    qb.and_where(Expr::col((j.clone(), CreatedAt)).lte(until));
+    if retained {
+      let mut sq = Query::select();
+      sq.from_as(Table, l.clone());
+      sq.expr(Expr::val(1));
+      sq.and_where(Expr::col((l.clone(), GuildId)).equals((j.clone(), GuildId)));
+      sq.and_where(Expr::col((l.clone(), UserId)).equals((j.clone(), UserId)));
+      sq.and_where(Expr::col((l.clone(), Kind)).eq(MemberEvent::Leave.as_str()));
+      sq.and_where(Expr::col((l.clone(), CreatedAt)).gte(Expr::col((j.clone(), CreatedAt))));
+      sq.and_where(
+        Expr::col((l.clone(), CreatedAt)).lte(Expr::col((j.clone(), CreatedAt)).add(days * 86400)),
+      );
+      qb.and_where(Expr::exists(sq).not());
+    }
+    qb
+  };
+  let total = fetch_one!(&joined(false), (i64,))?.0;
+  if total == 0 {
+    return Ok(None);
+  }
+  let retained = fetch_one!(&joined(true), (i64,))?.0;
+  Ok(Some(retained as f64 / total as f64))
+}
+
+/// Joins and leaves of the last `days` days, with the member count of each day if it's known
+pub async fn guild_stats(guild: GuildId, days: i64, members: Option<u64>) -> Res<GuildStats> {
+  let id = guild.0 as i64;
+  let to = Utc::now().timestamp() / 86400;
+  let from = to - days + 1;
+  let events = daily_events(id, from).await?;
+  // Joins are only attributed to members when every member is scraped
+  let every = scrape_settings(guild).await?.policy == ScrapePolicy::All;
+  let mut stats = GuildStats {
+    joins: events.values().map(|e| e.0).sum(),
+    leaves: events.values().map(|e| e.1).sum(),
+    retention_7d: match every {
+      true => retention(id, from, 7).await?,
+      false => None,
+    },
+    retention_30d: match every {
+      true => retention(id, from, 30).await?,
+      false => None,
+    },
+    days: vec![],
+  };
+  // Walk back from the current member count, undoing each day's changes
+  let mut count = members.map(|m| m as i64);
+  for day in (from..=to).rev() {
+    let (joins, leaves) = *events.get(&day).unwrap_or(&(0, 0));
+    stats.days.push(DayStats {
+      day: fmt_day(day),
+      joins,
+      leaves,
+      members: count,
+    });
+    count = count.map(|c| c - joins + leaves);
+  }
+  stats.days.reverse();
+  Ok(stats)
+}
+
+fn fmt_day(day: i64) -> String {
+  let date = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default() + Duration::days(day);
+  date.format("%Y-%m-%d").to_string()
+}
+
+fn fmt_share(share: Option<f64>) -> String {
+  share.map_or("-".into(), |s| format!("{:.1}%", s * 100.0))
+}
+
+cmd_group!(server, "stats");
+
+#[poise::command(prefix_command, slash_command, guild_only)]
+pub async fn stats(ctx: Ctx<'_>, days: Option<u32>, theme: Option<Theme>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  if !check_guild_whitelist(guild).await? {
+    ctx.reply("Members of this guild aren't tracked").await?;
+    return Ok(());
+  }
+  ctx.defer().await?;
+  let days = days.unwrap_or(30).clamp(1, 365) as i64;
+  let members = ctx.guild().map(|g| g.member_count);
+  let stats = guild_stats(guild, days, members).await?;
+  let chart = Chart {
+    theme: theme.unwrap_or(Theme::Dark),
+    title: format!("Members over the last {days} days"),
+    x_label: "Day".into(),
+    y_label: "Members".into(),
+    points: (stats.days.iter())
+      .map(|d| (d.day[5..].to_string(), d.members.unwrap_or(0) as f64))
+      .collect(),
+  };
+  let png = chart.render()?;
+  ctx
+    .send(|b| {
+      b.embed(|e| {
+        e.title(format!("Server stats of the last {days} days"))
+          .field("Joins", stats.joins, true)
+          .field("Leaves", stats.leaves, true)
+          .field("Net", stats.joins - stats.leaves, true)
+          .field("7 day retention", fmt_share(stats.retention_7d), true)
+          .field("30 day retention", fmt_share(stats.retention_30d), true)
+          .image("attachment://growth.png")
+      })
+      .attachment(AttachmentType::Bytes {
+        data: png.into(),
+        filename: "growth.png".into(),
+      })
+    })
+    .await?;
+  Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct StatsParams {
+  days: Option<u32>,
+}
+
+// Bearer token dashboards have to send, the endpoint is disabled without one
+once_cell!(stats_token, STATS_TOKEN: Option<String>, {
+  std::env::var("DISCORD_STATS_TOKEN").ok().filter(|t| !t.is_empty())
+});
+
+/// Same stats as `/server stats`, as json for dashboards
+pub async fn stats_json(
+  Path(guild): Path<u64>,
+  headers: HeaderMap,
+  Form(q): Form<StatsParams>,
+) -> axum::response::Result<Response> {
+  let Some(token) = stats_token().await else {
+    return Ok(StatusCode::NOT_FOUND.into_response());
+  };
+  let auth = headers
+    .get(header::AUTHORIZATION)
+    .and_then(|h| h.to_str().ok())
+    .and_then(|h| h.strip_prefix("Bearer "));
+  if auth != Some(token.as_str()) {
+    return Ok(StatusCode::UNAUTHORIZED.into_response());
+  }
+  let guild = GuildId(guild);
+  match check_guild_whitelist(guild).await {
+    Ok(true) => {}
+    Ok(false) => return Ok(StatusCode::NOT_FOUND.into_response()),
+    Err(err) => {
+      log::error!("Error when checking whitelist: {err}");
+      return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  }
+  let days = q.days.unwrap_or(30).clamp(1, 365) as i64;
+  let Some(c) = try_sctx() else {
+    return Ok(StatusCode::SERVICE_UNAVAILABLE.into_response());
+  };
+  let members = c.cache.guild_field(guild, |g| g.member_count);
+  Ok(match guild_stats(guild, days, members).await {
+    Ok(stats) => Json(stats).into_response(),
+    Err(err) => {
+      log::error!("Error when getting stats of {guild}: {err}");
+      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+  })
+}
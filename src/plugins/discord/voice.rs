@@ -0,0 +1,194 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::{
+  core::{
+    time::{fmt_sec, Period},
+    *,
+  },
+  modules::{
+    poise::{paginate, Ctx},
+    sqlx::count_rows,
+  },
+  plugins::discord::{schema::*, settings::scrape_settings, whitelist::check_guild_whitelist},
+};
+use chrono::Utc;
+use poise::serenity_prelude::{ChannelId, Guild, GuildId, UserId, VoiceState};
+use sea_query::{Alias, Expr, Func, OnConflict, Order, Query, SimpleExpr};
+
+const SIZE: u64 = 15;
+
+// Seconds spent in a session since a timestamp, counting open ones up to now
+fn duration(since: i64) -> SimpleExpr {
+  use VoiceSessions::*;
+  Expr::expr(Func::coalesce([
+    Expr::col(LeftAt).into(),
+    Expr::val(Utc::now().timestamp()).into(),
+  ]))
+  .sub(
+    Func::cust(Alias::new("GREATEST")).args([Expr::col(JoinedAt).into(), Expr::val(since).into()]),
+  )
+}
+
+fn total_duration(since: i64) -> SimpleExpr {
+  Func::cast_as(Func::sum(duration(since)), Alias::new("BIGINT")).into()
+}
+
+// Closes the open sessions of a member that aren't in the channel, and opens one in it if needed
+async fn set_channel(guild: GuildId, user: UserId, channel: Option<ChannelId>) -> R {
+  use VoiceSessions::*;
+  let (g, u) = (guild.0 as i64, user.0 as i64);
+  let now = Utc::now().timestamp();
+  let mut qb = Query::update();
+  qb.table(Table);
+  qb.value(LeftAt, now);
+  qb.and_where(Expr::col(GuildId).eq(g));
+  qb.and_where(Expr::col(UserId).eq(u));
+  qb.and_where(Expr::col(LeftAt).is_null());
+  if let Some(channel) = channel {
+    qb.and_where(Expr::col(ChannelId).ne(channel.0 as i64));
+  }
+  execute!(&qb)?;
+  let Some(channel) = channel else {
+    return Ok(());
+  };
+  // Only one session can be open per member, so this is a no-op if it's still open in the channel
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, UserId, ChannelId, JoinedAt]);
+  qb.values([g.into(), u.into(), (channel.0 as i64).into(), now.into()])?;
+  qb.on_conflict(OnConflict::new().do_nothing().to_owned());
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Track a voice state change, members not allowed by the scrape policy only get their sessions closed
+pub async fn update_voice(state: &VoiceState) -> R {
+  let Some(guild) = state.guild_id else {
+    return Ok(());
+  };
+  if !check_guild_whitelist(guild).await? {
+    return Ok(());
+  }
+  let tracked = match &state.member {
+    Some(m) => !m.user.bot && scrape_settings(guild).await?.allows(m),
+    None => false,
+  };
+  let channel = state.channel_id.filter(|_| tracked);
+  set_channel(guild, state.user_id, channel).await
+}
+
+/// Reconcile sessions with the members currently in voice, as updates are missed while offline
+pub async fn reconcile_voice(g: &Guild) -> R {
+  let settings = scrape_settings(g.id).await?;
+  let mut present = vec![];
+  for state in g.voice_states.values() {
+    let Some(channel) = state.channel_id else {
+      continue;
+    };
+    let Some(member) = g.members.get(&state.user_id) else {
+      continue;
+    };
+    if !member.user.bot && settings.allows(member) {
+      set_channel(g.id, state.user_id, Some(channel)).await?;
+      present.push(state.user_id.0 as i64);
+    }
+  }
+  // There's no way to know when they left, so they're counted up to now
+  use VoiceSessions::*;
+  let mut qb = Query::update();
+  qb.table(Table);
+  qb.value(LeftAt, Utc::now().timestamp());
+  qb.and_where(Expr::col(GuildId).eq(g.id.0 as i64));
+  qb.and_where(Expr::col(LeftAt).is_null());
+  if !present.is_empty() {
+    qb.and_where(Expr::col(UserId).is_not_in(present));
+  }
+  let closed = execute!(&qb)?.rows_affected();
+  if closed > 0 {
+    log::info!("Closed {closed} stale voice sessions in {}", g.id);
+  }
+  Ok(())
+}
+
+/// Seconds a user spent in voice, in the guild or across all of them
+pub async fn voice_total(user: UserId, guild: Option<GuildId>) -> Res<i64> {
+  use VoiceSessions::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.expr(Func::coalesce([total_duration(0), Expr::val(0).into()]));
+  qb.and_where(Expr::col(UserId).eq(user.0 as i64));
+  if let Some(guild) = guild {
+    qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+  }
+  Ok(fetch_one!(&qb, (i64,))?.0)
+}
+
+/// Prometheus metrics of every tracked guild, total voice time and members currently in voice
+pub async fn voice_metrics() -> Res<String> {
+  use VoiceSessions::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(GuildId);
+  qb.expr(total_duration(0));
+  qb.expr(Func::sum(
+    Expr::case(Expr::col(LeftAt).is_null(), 1).finally(0),
+  ));
+  qb.group_by_col(GuildId);
+  let mut output = String::new();
+  for (guild, seconds, active) in fetch_all!(&qb, (i64, i64, i64))? {
+    output += &format!("discord_voice_seconds_total{{guild=\"{guild}\"}} {seconds}\n");
+    output += &format!("discord_voice_members{{guild=\"{guild}\"}} {active}\n");
+  }
+  Ok(output)
+}
+
+cmd_group!(voice, "top");
+
+#[poise::command(prefix_command, slash_command, guild_only)]
+pub async fn top(ctx: Ctx<'_>, period: Option<Period>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  let period = period.unwrap_or(Period::Month);
+  let since = period.span().from as i64 * 86400;
+  let qb = {
+    use VoiceSessions::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(UserId);
+    qb.expr_as(total_duration(since), Alias::new("total"));
+    qb.and_where(Expr::col(GuildId).eq(guild.0 as i64));
+    // Sessions that overlap the period, only counting the part within it
+    qb.and_where(Expr::col(LeftAt).is_null().or(Expr::col(LeftAt).gt(since)));
+    qb.group_by_col(UserId);
+    qb.order_by(Alias::new("total"), Order::Desc);
+    qb.order_by(UserId, Order::Asc);
+    qb
+  };
+  let input = format!(
+    "Members that spent the most time in voice over the last {}",
+    period.to_string().to_lowercase()
+  );
+  paginate(ctx, count_rows(qb.clone()), SIZE, |page| {
+    let mut pb = qb.clone();
+    pb.limit(SIZE);
+    pb.offset(page * SIZE);
+    let input = input.clone();
+    async move {
+      let data: Vec<_> = fetch_all!(&pb, (i64, i64))?
+        .into_iter()
+        .map(|(user, total)| (user, fmt_sec(total)))
+        .collect();
+      let max = data.iter().map(|d| d.1.len()).max().unwrap_or(5);
+      let mut output = String::new();
+      for (user, total) in data {
+        output += &format!("`{total: >max$}` <@{user}>\n");
+      }
+      if output.is_empty() {
+        output = "Nobody was in voice yet\n".into();
+      }
+      Ok(format!("{input}:\n{output}"))
+    }
+  })
+  .await
+}
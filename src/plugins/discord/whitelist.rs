@@ -0,0 +1,245 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::{core::*, modules::poise::Ctx, plugins::discord::schema::WhitelistRequests};
+use chrono::Utc;
+use poise::{
+  serenity_prelude::{self as serenity, ChannelId, Context, Guild, GuildId, HttpError},
+  AutocompleteChoice,
+};
+use sea_query::{Expr, OnConflict, Query};
+use std::{
+  collections::HashSet,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    RwLock,
+  },
+};
+
+// Discord error code of guilds the bot isn't in
+const UNKNOWN_GUILD: isize = 10004;
+
+once_cell!(owner_channel, OWNER_CHANNEL: Option<ChannelId>);
+once_cell!(whitelist_grace, WHITELIST_GRACE: Option<i64>);
+
+/// Read the request channel and auto-leave grace period, both are optional
+pub fn init_whitelist() -> R {
+  OWNER_CHANNEL.set(
+    std::env::var("DISCORD_OWNER_CHANNEL")
+      .ok()
+      .and_then(|c| c.parse().ok())
+      .map(ChannelId),
+  )?;
+  // In hours
+  WHITELIST_GRACE.set(
+    std::env::var("DISCORD_WHITELIST_GRACE")
+      .ok()
+      .and_then(|g| g.parse().ok()),
+  )?;
+  Ok(())
+}
+
+// Whitelisted guilds, loaded on first use and dropped whenever the whitelist changes
+static CACHE: RwLock<Option<HashSet<GuildId>>> = RwLock::new(None);
+// Bumped on every change, so a load that raced with one doesn't get cached
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+async fn whitelisted_guilds() -> Res<HashSet<GuildId>> {
+  use crate::plugins::neko::schema::WhitelistDiscord::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(GuildId);
+  Ok(
+    fetch_all!(&qb, (i64,))?
+      .into_iter()
+      .map(|g| poise::serenity_prelude::GuildId(g.0 as u64))
+      .collect(),
+  )
+}
+
+pub async fn check_guild_whitelist(id: GuildId) -> Res<bool> {
+  let cached = (CACHE.read().unwrap_or_else(|e| e.into_inner()))
+    .as_ref()
+    .map(|c| c.contains(&id));
+  if let Some(whitelisted) = cached {
+    return Ok(whitelisted);
+  }
+  let generation = GENERATION.load(Ordering::SeqCst);
+  let guilds = whitelisted_guilds().await?;
+  let whitelisted = guilds.contains(&id);
+  let mut cache = CACHE.write().unwrap_or_else(|e| e.into_inner());
+  if GENERATION.load(Ordering::SeqCst) == generation {
+    *cache = Some(guilds);
+  }
+  Ok(whitelisted)
+}
+
+fn invalidate() {
+  let mut cache = CACHE.write().unwrap_or_else(|e| e.into_inner());
+  GENERATION.fetch_add(1, Ordering::SeqCst);
+  *cache = None;
+}
+
+/// Add or remove a guild from the whitelist, returning whether anything changed
+pub async fn set_whitelisted(id: GuildId, whitelisted: bool) -> Res<bool> {
+  use crate::plugins::neko::schema::WhitelistDiscord::*;
+  let res = if whitelisted {
+    let mut qb = Query::insert();
+    qb.into_table(Table);
+    qb.columns([GuildId]);
+    qb.values([(id.0 as i64).into()])?;
+    qb.on_conflict(OnConflict::column(GuildId).do_nothing().to_owned());
+    execute!(&qb)?
+  } else {
+    let mut qb = Query::delete();
+    qb.from_table(Table);
+    qb.and_where(Expr::col(GuildId).eq(id.0 as i64));
+    execute!(&qb)?
+  };
+  invalidate();
+  remove_request(id).await?;
+  Ok(res.rows_affected() > 0)
+}
+
+pub async fn remove_request(id: GuildId) -> R {
+  use WhitelistRequests::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.and_where(Expr::col(GuildId).eq(id.0 as i64));
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Ask the owners to whitelist a guild the bot just joined
+pub async fn request_access(c: &Context, g: &Guild) -> R {
+  use WhitelistRequests::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, RequestedAt]);
+  qb.values([(g.id.0 as i64).into(), Utc::now().timestamp().into()])?;
+  qb.on_conflict(OnConflict::column(GuildId).do_nothing().to_owned());
+  execute!(&qb)?;
+  let Some(channel) = owner_channel() else {
+    return Ok(());
+  };
+  let mut content = format!(
+    "Joined **{}** (`{}`) owned by <@{}>, with {} members\nWhitelist it with `/whitelist add {}`",
+    g.name, g.id, g.owner_id, g.member_count, g.id
+  );
+  if let Some(grace) = whitelist_grace() {
+    content += &format!(", or the bot will leave it in {grace} hours");
+  }
+  channel
+    .send_message(c, |m| {
+      m.content(content).allowed_mentions(|a| a.empty_parse())
+    })
+    .await?;
+  Ok(())
+}
+
+/// Leave guilds that weren't whitelisted within the grace period
+pub async fn leave_expired(c: &Context) -> R {
+  let Some(grace) = whitelist_grace() else {
+    return Ok(());
+  };
+  use WhitelistRequests::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(GuildId);
+  qb.and_where(Expr::col(RequestedAt).lt(Utc::now().timestamp() - grace * 3600));
+  for (id,) in fetch_all!(&qb, (i64,))? {
+    let id = poise::serenity_prelude::GuildId(id as u64);
+    if !check_guild_whitelist(id).await? {
+      log::info!("Leaving {id}, as it wasn't whitelisted in time");
+      // The request is kept, so leaving is retried next time, unless the guild is already gone
+      match id.leave(c).await {
+        Ok(()) => {}
+        Err(serenity::Error::Http(err)) if matches!(
+          &*err,
+          HttpError::UnsuccessfulRequest(res) if res.error.code == UNKNOWN_GUILD
+        ) => {}
+        Err(err) => {
+          log::warn!("Failed to leave {id}: {err}");
+          continue;
+        }
+      }
+    }
+    remove_request(id).await?;
+  }
+  Ok(())
+}
+
+// Guilds the bot is in, unlike discord_guilds which only has whitelisted ones
+async fn joined_guilds(ctx: Ctx<'_>, search: &str) -> Vec<AutocompleteChoice<String>> {
+  let cache = &ctx.serenity_context().cache;
+  let search = search.to_lowercase();
+  cache
+    .guilds()
+    .into_iter()
+    .filter_map(|g| Some((g, cache.guild_field(g, |g| g.name.clone())?)))
+    .filter(|(g, name)| name.to_lowercase().contains(&search) || g.to_string().contains(&search))
+    .take(25)
+    .map(|(g, name)| AutocompleteChoice {
+      value: g.to_string(),
+      name,
+    })
+    .collect()
+}
+
+cmd_group!(whitelist, "add", "remove", "list");
+
+#[poise::command(prefix_command, slash_command, hide_in_help, owners_only)]
+pub async fn add(ctx: Ctx<'_>, #[autocomplete = "joined_guilds"] guild: String) -> R {
+  let id = GuildId(guild.parse()?);
+  if !set_whitelisted(id, true).await? {
+    ctx.reply("The guild is already whitelisted").await?;
+    return Ok(());
+  }
+  ctx.defer().await?;
+  // Same as the bot joining a whitelisted guild
+  if ctx.serenity_context().cache.guild(id).is_some() {
+    super::update_guild(ctx.serenity_context(), id).await?;
+    super::scrape_guild(ctx.serenity_context(), id).await?;
+  }
+  ctx.reply(format!("Whitelisted `{id}`")).await?;
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, hide_in_help, owners_only)]
+pub async fn remove(ctx: Ctx<'_>, #[autocomplete = "joined_guilds"] guild: String) -> R {
+  let id = GuildId(guild.parse()?);
+  if !set_whitelisted(id, false).await? {
+    ctx.reply("The guild isn't whitelisted").await?;
+    return Ok(());
+  }
+  // Removing guild cascades to removing all guild members
+  super::remove_guild(id).await?;
+  ctx
+    .reply(format!("Removed `{id}` from the whitelist"))
+    .await?;
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, hide_in_help, owners_only)]
+pub async fn list(ctx: Ctx<'_>) -> R {
+  let cache = &ctx.serenity_context().cache;
+  let mut guilds: Vec<_> = whitelisted_guilds()
+    .await?
+    .into_iter()
+    .map(|g| match cache.guild_field(g, |g| g.name.clone()) {
+      Some(name) => format!("{name} (`{g}`)"),
+      None => format!("`{g}` (not joined)"),
+    })
+    .collect();
+  guilds.sort();
+  let output = if guilds.is_empty() {
+    "No guilds are whitelisted".into()
+  } else {
+    guilds.join("\n")
+  };
+  ctx
+    .send(|b| b.embed(|e| e.title("Whitelisted guilds").description(output)))
+    .await?;
+  Ok(())
+}
@@ -0,0 +1,130 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::core::*;
+use askama::Template;
+use axum::{
+  http::HeaderValue,
+  response::{IntoResponse, Response},
+};
+use axum_session::SessionPgSession;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use reqwest::{header, StatusCode};
+use sea_query::{Expr, OnConflict, Query};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// Authorizations not finished within this many seconds have to be started over
+const STATE_TTL: i64 = 600;
+// Steam OpenID responses older than this are rejected, so nonces only need to be kept as long
+const NONCE_TTL: i64 = 300;
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorPage<'a> {
+  message: &'a str,
+}
+
+pub fn error_page(status: StatusCode, message: &str) -> Response {
+  let page = ErrorPage { message }
+    .render()
+    .unwrap_or(message.to_string());
+  let mut res = (status, page).into_response();
+  res
+    .headers_mut()
+    .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
+  res
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingAuth {
+  state: String,
+  verifier: Option<String>,
+  expires_at: i64,
+}
+
+/// Values to send along with the authorization request
+pub struct Authorization {
+  pub state: String,
+  // PKCE challenge, S256 of the verifier that stays in the session
+  pub challenge: Option<String>,
+}
+
+impl Authorization {
+  pub fn query(&self) -> String {
+    let mut query = format!("&state={}", self.state);
+    if let Some(challenge) = &self.challenge {
+      query += &format!("&code_challenge={challenge}&code_challenge_method=S256");
+    }
+    query
+  }
+}
+
+// 122 random bits per uuid
+fn random(uuids: usize) -> String {
+  (0..uuids)
+    .map(|_| Uuid::new_v4().simple().to_string())
+    .collect()
+}
+
+/// Start an authorization with the provider, remembering its state in the session
+pub fn begin(session: &SessionPgSession, provider: &str, pkce: bool) -> Authorization {
+  let state = random(1);
+  // Verifiers have to be 43 to 128 characters long
+  let verifier = pkce.then(|| random(2));
+  let challenge = (verifier.as_ref()).map(|v| URL_SAFE_NO_PAD.encode(Sha256::digest(v.as_bytes())));
+  session.set(
+    &format!("oauth_{provider}"),
+    PendingAuth {
+      state: state.clone(),
+      verifier,
+      expires_at: Utc::now().timestamp() + STATE_TTL,
+    },
+  );
+  Authorization { state, challenge }
+}
+
+/// Check the state returned by the provider against the one in the session, returning the PKCE verifier,
+/// or why it can't be used. Each state can only be used once
+pub fn verify(
+  session: &SessionPgSession,
+  provider: &str,
+  state: &str,
+) -> Result<Option<String>, &'static str> {
+  let key = format!("oauth_{provider}");
+  let pending = session.get::<PendingAuth>(&key);
+  session.remove(&key);
+  match pending {
+    Some(p) if p.state == state && p.expires_at >= Utc::now().timestamp() => Ok(p.verifier),
+    Some(p) if p.state == state => Err("the link expired, please try again"),
+    _ => Err("this link wasn't started by you, please try again"),
+  }
+}
+
+/// Remember a Steam OpenID response nonce, failing if it's too old or was already used
+pub async fn use_nonce(nonce: &str) -> Res<bool> {
+  // Nonces start with the time they were issued at, like 2024-01-01T00:00:00Z
+  let Some(issued) = nonce
+    .get(..20)
+    .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+  else {
+    return Ok(false);
+  };
+  let now = Utc::now().timestamp();
+  if (now - issued.timestamp()).abs() > NONCE_TTL {
+    return Ok(false);
+  }
+  use super::OpenidNonces::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.and_where(Expr::col(UsedAt).lt(now - NONCE_TTL * 2));
+  execute!(&qb)?;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([Nonce, UsedAt]);
+  qb.values([nonce.into(), now.into()])?;
+  qb.on_conflict(OnConflict::column(Nonce).do_nothing().to_owned());
+  Ok(execute!(&qb)?.rows_affected() > 0)
+}
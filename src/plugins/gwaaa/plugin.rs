@@ -1,25 +1,31 @@
-use std::collections::HashMap;
-
 use crate::{
-  core::{Err, Res},
-  modules::{axum::Axum, reqwest::req, sqlx::db},
-  plugins::steam::query::{update_playdata, update_users},
+  core::Res,
+  modules::{axum::Axum, sqlx::db},
+  plugins::{
+    discord::voice::voice_metrics,
+    steam::query::{fetch_states, ProfileState},
+  },
 };
 use askama::Template;
 use axum::{
   http::HeaderValue,
   response::{IntoResponse, Redirect, Response},
   routing::get,
-  Form, Json,
+  Form,
 };
 use axum_session::{SessionConfig, SessionLayer, SessionPgSession, SessionPgSessionStore};
-use poise::serenity_prelude::{json::json, UserId};
+use poise::serenity_prelude::UserId;
 use regex::Regex;
 use reqwest::{header, StatusCode};
-use sea_query::{Alias, Expr, Func, Iden, InsertStatement, OnConflict, Query, SelectStatement};
-use url::Url;
+use sea_query::{Alias, Expr, Func, Iden, Query, SelectStatement};
 use uuid::Uuid;
 
+use self::provider::{callback, link, set_providers, LinkProvider};
+
+pub mod oauth;
+pub mod provider;
+mod providers;
+
 #[derive(Template)]
 #[template(path = "login.html")]
 struct LoginPage;
@@ -27,13 +33,47 @@ struct LoginPage;
 #[derive(Template)]
 #[template(path = "settings.html")]
 struct SettingsPage {
-  id: i32
+  id: i32,
+  steam: Vec<SteamAccount>,
+}
+
+struct SteamAccount {
+  id: i64,
+  state: &'static str,
+  ok: bool,
+  private: bool,
+  checked: Option<String>,
+}
+
+async fn steam_accounts(id: i32) -> Vec<SteamAccount> {
+  let states = match fetch_states(id).await {
+    Ok(states) => states,
+    Err(err) => {
+      log::warn!("Failed to get Steam fetch states of neko user {id}: {err}");
+      return vec![];
+    }
+  };
+  states
+    .into_iter()
+    .map(|(id, state, checked_at)| {
+      let state = state.as_deref().and_then(ProfileState::parse);
+      SteamAccount {
+        id,
+        state: state.map_or("not checked yet", |s| s.describe()),
+        ok: state == Some(ProfileState::Ok),
+        private: state == Some(ProfileState::Private),
+        checked: checked_at
+          .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+          .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string()),
+      }
+    })
+    .collect()
 }
 
 async fn root(session: SessionPgSession) -> Response {
   let mut res = match session.get::<i32>("neko_id") {
     Some(id) => 
-    SettingsPage {id}.render(),
+    SettingsPage {id, steam: steam_accounts(id).await}.render(),
     None => LoginPage.render()
   }.unwrap().into_response();
   res
@@ -69,20 +109,19 @@ async fn login_now(session: SessionPgSession) -> Response {
   }
 }
 
-once_cell!(sid_regex, REGEX: Regex);
 once_cell!(mc_regex, MCNAMEREGEX: Regex);
 
+/// Account linking website, other plugins can add their own link providers
 #[derive(Default)]
-pub struct Gwaaa;
+pub struct Gwaaa {
+  pub providers: Vec<Box<dyn LinkProvider>>,
+}
 
 impl crate::core::Module for Gwaaa {
   async fn init(&mut self, fw: &mut crate::core::Framework) -> crate::core::R {
     {
       fw.req_module::<crate::modules::reqwest::Reqwest>().await?;
       fw.req_module::<crate::modules::sqlx::Postgres>().await?;
-      REGEX.set(Regex::new(
-        "^https://steamcommunity.com/openid/id/([0-9]{17})$",
-      )?)?;
       MCNAMEREGEX.set(Regex::new(
         "[^a-zA-Z0-9_].",
       )?)?;
@@ -101,21 +140,19 @@ impl crate::core::Module for Gwaaa {
               .route("/login", get(login_now))
               .route("/logout", get(logout))
               .route("/whitelist", get(whitelist))
-              .route("/callback/anilist", get(callback_anilist))
-              .route("/callback/github", get(callback_github))
-              .route("/callback/steam", get(callback_steam))
-              .route("/callback/discord", get(callback_discord))
-              .route("/callback/minecraft", get(callback_minecraft))
-              .route("/link/anilist", get(link_anilist))
-              .route("/link/github", get(link_github))
-              .route("/link/steam", get(link_steam))
-              .route("/link/discord", get(link_discord))
-              .route("/link/minecraft", get(link_minecraft))
+              .route("/callback/:provider", get(callback))
+              .route("/link/:provider", get(link))
               .layer(SessionLayer::new(session_store))
               .route("/metrics", get(metrics)),
           )
         })
       });
+
+      self.providers.extend(providers::builtin());
+      runtime!(fw, |m| {
+        set_providers(m.providers);
+        Ok(None)
+      });
     }
     Ok(())
   }
@@ -146,188 +183,16 @@ async fn metrics() -> String {
     }
     Result::Err(err) => log::warn!("{}", err),
   }
-
-  output
-}
-
-struct GenericError(Err);
-
-impl From<Err> for GenericError {
-  fn from(value: Err) -> Self {
-    Self(value)
-  }
-}
-
-impl IntoResponse for GenericError {
-  fn into_response(self) -> Response {
-    (
-      StatusCode::INTERNAL_SERVER_ERROR,
-      Json(json!({
-        "error": self.0.to_string(),
-      })),
-    )
-      .into_response()
-  }
-}
-
-async fn link_steam() -> axum::response::Result<Response> {
-  let mut redirect_url = Url::parse("https://steamcommunity.com/openid/login").unwrap();
-  redirect_url.set_query(Some(
-    &serde_urlencoded::to_string(&RedirectForm {
-      ns: "http://specs.openid.net/auth/2.0",
-      identity: "http://specs.openid.net/auth/2.0/identifier_select",
-      claimed_id: "http://specs.openid.net/auth/2.0/identifier_select",
-      mode: "checkid_setup",
-      realm: root_domain().await,
-      return_to: &format!("{}/callback/steam", root_domain().await),
-    })
-    .unwrap(),
-  ));
-  Ok(Redirect::to(redirect_url.as_str()).into_response())
-}
-
-// who needs actual error handling tbh
-async fn callback_steam(
-  session: SessionPgSession,
-  Form(cb): Form<VerifyForm>
-) -> axum::response::Result<Response> {
-  let Some(id) = session.get::<i32>("neko_id") else {
-    return Ok(Redirect::to("/login").into_response());
-  };
-  let mut validate = cb;
-  validate.mode = "check_authentication".to_owned();
-  let form_str = serde_urlencoded::to_string(&validate).unwrap();
-
-  let response = req()
-    .post("https://steamcommunity.com/openid/login")
-    .header("Content-Type", "application/x-www-form-urlencoded")
-    .body(form_str)
-    .send()
-    .await
-    .unwrap()
-    .text()
-    .await
-    .unwrap();
-
-  let is_valid = response.split('\n').any(|line| line == "is_valid:true");
-  if !is_valid {
-    return Err("NOT VALID GWAAAA".into());
+  match voice_metrics().await {
+    Ok(voice) => output += &voice,
+    Result::Err(err) => log::warn!("{}", err),
   }
 
-  let captures = sid_regex().captures(&validate.claimed_id).unwrap();
-  let steam_id = captures.get(1).unwrap().as_str().parse::<i64>().unwrap();
-  use crate::plugins::neko::schema::UsersSteam::*;
-  let mut qb = InsertStatement::new();
-  qb.into_table(Table);
-  qb.columns([NekoId, SteamId]);
-  qb.values([id.into(), steam_id.into()]).unwrap();
-  qb.on_conflict(OnConflict::column(SteamId).update_column(NekoId).to_owned());
-  execute!(&qb).unwrap();
-
-  let c = vec![(steam_id,)];
-  update_users(&c).await.unwrap();
-  update_playdata(&c).await.unwrap();
-  Ok(Redirect::to("/").into_response())
+  output
 }
 
 once_cell!(root_domain, ROOT_DOMAIN: String, {expect_env!("ROOT_DOMAIN")});
 
-once_cell!(oauth_discord_id, OAUTH_DISCORD_ID: String, {expect_env!("OAUTH_DISCORD_ID")});
-once_cell!(oauth_discord_secret, OAUTH_DISCORD_SECRET: String, {expect_env!("OAUTH_DISCORD_SECRET")});
-once_cell!(redirect_discord, REDIRECT_DISCORD: String, {
-  let cb = format!("{}/callback/discord", root_domain().await);
-  format!("https://discord.com/oauth2/authorize\
-  ?client_id={}&redirect_uri={}&response_type=code\
-  &scope=identify&prompt=consent&state=todo",
-  oauth_discord_id().await, urlencoding::encode(&cb))
-});
-
-once_cell!(oauth_github_id, OAUTH_GITHUB_ID: String, {expect_env!("OAUTH_GITHUB_ID")});
-once_cell!(oauth_github_secret, OAUTH_GITHUB_SECRET: String, {expect_env!("OAUTH_GITHUB_SECRET")});
-
-once_cell!(redirect_github, REDIRECT_GITHUB: String, {
-  let cb = format!("{}/callback/github", root_domain().await);
-  format!("https://github.com/login/oauth/authorize\
-  ?client_id={}&redirect_uri={}&response_type=code\
-  &allow_signup=false&state=todo", oauth_github_id().await,
-  urlencoding::encode(&cb))
-});
-
-once_cell!(tokenreq_github, TOKENREQ_GITHUB: String, {
-  let cb = format!("{}/callback/github", root_domain().await);
-  format!("https://github.com/login/oauth/access_token\
-  ?client_id={}&client_secret={}&redirect_uri={}",
-  oauth_github_id().await, oauth_github_secret().await,
-  urlencoding::encode(&cb))
-});
-
-once_cell!(oauth_anilist_id, OAUTH_ANILIST_ID: String, {expect_env!("OAUTH_ANILIST_ID")});
-once_cell!(oauth_anilist_secret, OAUTH_ANILIST_SECRET: String, {expect_env!("OAUTH_ANILIST_SECRET")});
-
-once_cell!(oauth_minecraft_id, OAUTH_MINECRAFT_ID: String, {expect_env!("OAUTH_MINECRAFT_ID")});
-once_cell!(oauth_minecraft_secret, OAUTH_MINECRAFT_SECRET: String, {expect_env!("OAUTH_MINECRAFT_SECRET")});
-
-once_cell!(redirect_anilist, REDIRECT_ANILIST: String, {
-  let cb = format!("{}/callback/anilist", root_domain().await);
-  format!("https://anilist.co/api/v2/oauth/authorize\
-  ?client_id={}&redirect_uri={}&response_type=code&state=todo", oauth_anilist_id().await,
-  urlencoding::encode(&cb))
-});
-
-once_cell!(redirect_minecraft, REDIRECT_MINECRAFT: String, {
-  let cb = format!("{}/callback/minecraft", root_domain().await);
-  format!("https://mc-auth.com/oAuth2/authorize\
-  ?client_id=3551875741534651542&redirect_uri={}&response_type=code&scope=profile&state=todo",
-  urlencoding::encode(&cb))
-});
-
-async fn link_anilist() -> axum::response::Result<Response> {
-  Ok(Redirect::to(redirect_anilist().await).into_response())
-}
-
-async fn link_minecraft() -> axum::response::Result<Response> {
-  Ok(Redirect::to(redirect_minecraft().await).into_response())
-}
-
-async fn callback_minecraft(
-  session: SessionPgSession,
-  Form(cb): Form<AuthorizationCallback>,
-) -> axum::response::Result<Response> {
-  let Some(id) = session.get::<i32>("neko_id") else {
-    return Ok(Redirect::to("/login").into_response());
-  };
-  if cb.state != "todo" {
-    return Ok(StatusCode::IM_A_TEAPOT.into_response());
-  }
-  let form_str = &DiscordTokenReq {
-    client_id: &"3551875741534651542",
-    client_secret: &expect_env!("OAUTH_MINECRAFT_SECRET"),
-    grant_type: &"authorization_code",
-    code: &cb.code,
-    redirect_uri: &format!("{}/callback/minecraft", root_domain().await),
-  };
-
-  let res = req()
-    .post("https://mc-auth.com/oAuth2/token")
-    .json(form_str)
-    .send()
-    .await.unwrap();
-
-  let Ok(response) =  res.json::<MCTokenRes>().await else {
-    return Err("NOT VALID GWAAAA".into());
-  };
-
-  use UsersMinecraft::*;
-  let mut qb = InsertStatement::new();
-  qb.into_table(Table);
-  qb.columns([NekoId, McUuid]);
-  qb.values([id.into(), response.data.uuid.into()]).unwrap();
-  qb.on_conflict(OnConflict::column(NekoId).do_nothing().to_owned());
-  execute!(&qb).unwrap();
-
-  Ok(Redirect::to("/").into_response())
-}
-
 async fn whitelist(Form(q): Form<Bruh>) -> axum::response::Result<Response> {
   println!("uuid: {}", q.uuid);
   let mut qb = SelectStatement::new();
@@ -373,6 +238,14 @@ pub enum UsersMinecraft {
   McUuid,
 }
 
+#[derive(Iden)]
+#[iden(rename = "neko_openid_nonces")]
+pub enum OpenidNonces {
+  Table,
+  Nonce,
+  UsedAt,
+}
+
 pub async fn get_mc_users() -> Res<Vec<UserId>> {
   let mut qb = Query::select();
   
@@ -391,306 +264,7 @@ pub async fn get_mc_users() -> Res<Vec<UserId>> {
   )
 }
 
-async fn link_discord() -> axum::response::Result<Response> {
-  Ok(Redirect::to(redirect_discord().await).into_response())
-}
-
-async fn link_github() -> axum::response::Result<Response> {
-  Ok(Redirect::to(redirect_github().await).into_response())
-}
-
-async fn callback_discord(
-  session: SessionPgSession,
-  Form(cb): Form<AuthorizationCallback>,
-) -> axum::response::Result<Response> {
-  if cb.state != "todo" {
-    return Ok(StatusCode::IM_A_TEAPOT.into_response());
-  }
-  let form_str = serde_urlencoded::to_string(&DiscordTokenReq {
-    client_id: &"1064379551318278204",
-    client_secret: &expect_env!("OAUTH_DISCORD_SECRET"),
-    grant_type: &"authorization_code",
-    code: &cb.code,
-    redirect_uri: &format!("{}/callback/discord", root_domain().await),
-  })
-  .unwrap();
-
-  let response = req()
-    .post("https://discord.com/api/v10/oauth2/token")
-    .header("Content-Type", "application/x-www-form-urlencoded")
-    .body(form_str)
-    .send()
-    .await
-    .unwrap()
-    .json::<TokenRes>()
-    .await
-    .unwrap();
-
-  let response = req()
-    .get("https://discord.com/api/v10/oauth2/@me")
-    .header("Content-Type", "application/json")
-    .bearer_auth(response.access_token)
-    .send()
-    .await
-    .unwrap()
-    .json::<DiscordAuthRes>()
-    .await
-    .unwrap();
-
-  if let None = response.user {
-    return Err("NOT VALID GWAAAA".into());
-  }
-
-  let did = response.user.unwrap().id.parse::<i64>().unwrap();
-
-  let id = match session.get::<i32>("neko_id") {
-    None => {
-      use crate::plugins::neko::schema::UsersDiscord;
-      let mut qb = SelectStatement::new();
-      qb.from(UsersDiscord::Table);
-      qb.column(UsersDiscord::NekoId);
-      qb.and_where(ex_col!(UsersDiscord, DiscordId).eq(did));
-      let newid = match fetch_optional!(&qb, (i32,)).unwrap() {
-        Some(id) => id.0,
-        None => {
-          use crate::plugins::neko::schema::Users::*;
-          let mut qb = InsertStatement::new();
-          qb.into_table(Table);
-          qb.columns([Slug]);
-          qb.values([Option::<String>::None.into()]).unwrap();
-          qb.returning(Query::returning().columns([Id]));
-          fetch_one!(&qb, (i32,)).unwrap().0
-        }
-      };
-      session.set("neko_id", newid);
-      newid
-    }
-    Some(id) => id,
-  };
-  use crate::plugins::neko::schema::UsersDiscord::*;
-  let mut qb = InsertStatement::new();
-  qb.into_table(Table);
-  qb.columns([NekoId, DiscordId]);
-  qb.values([id.into(), did.into()]).unwrap();
-  qb.on_conflict(
-    OnConflict::column(DiscordId)
-      .update_column(NekoId)
-      .to_owned(),
-  );
-  execute!(&qb).unwrap();
-  Ok(Redirect::to("/").into_response())
-}
-
-async fn callback_github(
-  session: SessionPgSession,
-  Form(cb): Form<AuthorizationCallback>,
-) -> axum::response::Result<Response> {
-  let Some(id) = session.get::<i32>("neko_id") else {
-    return Ok(Redirect::to("/login").into_response());
-  };
-  if cb.state != "todo" {
-    return Ok(StatusCode::IM_A_TEAPOT.into_response());
-  }
-
-  let response = req()
-    .post(format!("{}&code={}", tokenreq_github().await, cb.code))
-    .header("Content-Type", "application/x-www-form-urlencoded")
-    .header("Accept", "application/json")
-    .send()
-    .await
-    .unwrap()
-    .json::<TokenRes>()
-    .await
-    .unwrap();
-
-  let response = req()
-    .get("https://api.github.com/user")
-    .header("Content-Type", "application/json")
-    .header("X-GitHub-Api-Version", "2022-11-28")
-    .header("Accept", "application/vnd.github+json")
-    .bearer_auth(response.access_token)
-    .send()
-    .await
-    .unwrap()
-    .json::<GithubRes>()
-    .await
-    .unwrap();
-
-  let gid = response.id;
-  use crate::plugins::neko::schema::UsersGithub::*;
-  let mut qb = InsertStatement::new();
-  qb.into_table(Table);
-  qb.columns([NekoId, GithubId]);
-  qb.values([id.into(), gid.into()]).unwrap();
-  qb.on_conflict(
-    OnConflict::column(GithubId)
-      .update_column(GithubId)
-      .to_owned(),
-  );
-  execute!(&qb).unwrap();
-  Ok(Redirect::to("/").into_response())
-}
-
-async fn callback_anilist(
-  session: SessionPgSession,
-  Form(cb): Form<AuthorizationCallback>,
-) -> axum::response::Result<Response> {
-  let Some(id) = session.get::<i32>("neko_id") else {
-    return Ok(Redirect::to("/login").into_response());
-  };
-  if cb.state != "todo" {
-    return Ok(StatusCode::IM_A_TEAPOT.into_response());
-  }
-  let response = req()
-    .post("https://anilist.co/api/v2/oauth/token")
-    .header("Content-Type", "application/json")
-    .header("Accept", "application/json")
-    .json(&json!({
-      "client_id": oauth_anilist_id().await,
-      "client_secret": oauth_anilist_secret().await,
-      "grant_type": "authorization_code",
-      "redirect_uri": format!("{}/callback/anilist", root_domain().await),
-      "code": cb.code
-    }))
-    .send()
-    .await
-    .unwrap()
-    .json::<TokenRes>()
-    .await
-    .unwrap();
-
-  let response = req()
-    .post("https://graphql.anilist.co")
-    .header("Content-Type", "application/json")
-    .header("Accept", "application/json")
-    .bearer_auth(response.access_token)
-    .json(&json!({
-      "query": "{Viewer{id}}"
-    }))
-    .send()
-    .await
-    .unwrap()
-    .json::<AnilistRes>()
-    .await
-    .unwrap();
-  let gid = response.data.viewer.id;
-  use crate::plugins::neko::schema::UsersAnilist::*;
-  let mut qb = InsertStatement::new();
-  qb.into_table(Table);
-  qb.columns([NekoId, AnilistId]);
-  qb.values([id.into(), gid.into()]).unwrap();
-  qb.on_conflict(
-    OnConflict::column(AnilistId)
-      .update_column(AnilistId)
-      .to_owned(),
-  );
-  execute!(&qb).unwrap();
-  Ok(Redirect::to("/").into_response())
-}
-
-#[derive(serde::Serialize)]
-struct RedirectDiscord<'a> {
-  response_type: &'static str,
-  client_id: &'static str,
-  scope: &'static str,
-  state: &'a str,
-  redirect_uri: String,
-  prompt: &'a str,
-}
-
-#[derive(serde::Deserialize)]
-struct AuthorizationCallback {
-  code: String,
-  state: String,
-}
 #[derive(serde::Deserialize)]
 struct Bruh {
   uuid: Uuid
 }
-
-
-#[derive(serde::Serialize, Debug)]
-struct DiscordTokenReq<'a> {
-  client_id: &'a str,
-  client_secret: &'a str,
-  grant_type: &'a str,
-  code: &'a str,
-  redirect_uri: &'a str,
-}
-
-#[derive(serde::Deserialize)]
-struct MCTokenRes {
-  data: MCData,
-}
-#[derive(serde::Deserialize)]
-struct MCData {
-  uuid: Uuid,
-}
-#[derive(serde::Deserialize)]
-struct TokenRes {
-  access_token: String,
-}
-#[derive(serde::Deserialize)]
-struct DiscordAuthRes {
-  user: Option<DiscordUser>,
-}
-
-#[derive(serde::Deserialize)]
-struct AnilistRes {
-  data: AnilistData,
-}
-#[derive(serde::Deserialize)]
-struct AnilistData {
-  #[serde(rename = "Viewer")]
-  viewer: GithubRes,
-}
-#[derive(serde::Deserialize)]
-struct GithubRes {
-  id: i64,
-}
-#[derive(serde::Deserialize)]
-struct DiscordUser {
-  id: String,
-}
-
-#[derive(serde::Serialize)]
-struct RedirectForm<'a> {
-  #[serde(rename = "openid.ns")]
-  ns: &'static str,
-  #[serde(rename = "openid.identity")]
-  identity: &'static str,
-  #[serde(rename = "openid.claimed_id")]
-  claimed_id: &'static str,
-  #[serde(rename = "openid.mode")]
-  mode: &'static str,
-  #[serde(rename = "openid.return_to")]
-  return_to: &'a str,
-  #[serde(rename = "openid.realm")]
-  realm: &'a str,
-}
-
-#[derive(serde::Serialize, serde::Deserialize)]
-struct VerifyForm {
-  #[serde(rename = "openid.ns")]
-  ns: String,
-  #[serde(rename = "openid.mode")]
-  mode: String,
-  #[serde(rename = "openid.op_endpoint")]
-  op_endpoint: String,
-  #[serde(rename = "openid.claimed_id")]
-  claimed_id: String,
-  #[serde(rename = "openid.identity")]
-  identity: Option<String>,
-  #[serde(rename = "openid.return_to")]
-  return_to: String,
-  #[serde(rename = "openid.response_nonce")]
-  response_nonce: String,
-  #[serde(rename = "openid.invalidate_handle")]
-  invalidate_handle: Option<String>,
-  #[serde(rename = "openid.assoc_handle")]
-  assoc_handle: String,
-  #[serde(rename = "openid.signed")]
-  signed: String,
-  #[serde(rename = "openid.sig")]
-  sig: String,
-}
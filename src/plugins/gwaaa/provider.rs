@@ -0,0 +1,258 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::{
+  oauth::{begin, error_page, verify, Authorization},
+  root_domain,
+};
+use crate::core::*;
+use axum::{
+  extract::Path,
+  response::{IntoResponse, Redirect, Response},
+  Form,
+};
+use axum_session::SessionPgSession;
+use futures::future::BoxFuture;
+use reqwest::StatusCode;
+use sea_query::{DynIden, Expr, OnConflict, Query, Value};
+use std::{collections::HashMap, fmt};
+use tokio::sync::OnceCell;
+
+/// Query parameters the provider sent back to the callback
+pub type Params = HashMap<String, String>;
+
+/// Where the accounts of a provider are stored
+pub struct Storage {
+  pub table: DynIden,
+  pub neko_id: DynIden,
+  pub account_id: DynIden,
+  // What happens when the account is linked again
+  pub on_conflict: OnConflict,
+}
+
+/// Error that is safe to show to the user, anything else is only logged
+#[derive(Debug)]
+pub struct Rejected(pub &'static str);
+
+impl fmt::Display for Rejected {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.0)
+  }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Client credentials of an OAuth app
+pub struct OAuthClient {
+  pub id: String,
+  pub secret: String,
+}
+
+impl OAuthClient {
+  pub fn token_req<'a>(
+    &'a self,
+    params: &'a Params,
+    redirect_uri: &'a str,
+    verifier: Option<&'a str>,
+  ) -> Res<TokenReq<'a>> {
+    Ok(TokenReq {
+      client_id: &self.id,
+      client_secret: &self.secret,
+      grant_type: "authorization_code",
+      code: params
+        .get("code")
+        .ok_or(Rejected("the login didn't go through, please try again"))?,
+      redirect_uri,
+      code_verifier: verifier,
+    })
+  }
+}
+
+/// Authorization code grant, sent as a form or json depending on the provider
+#[derive(serde::Serialize)]
+pub struct TokenReq<'a> {
+  client_id: &'a str,
+  client_secret: &'a str,
+  grant_type: &'a str,
+  code: &'a str,
+  redirect_uri: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  code_verifier: Option<&'a str>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TokenRes {
+  pub access_token: String,
+}
+
+/// Account provider that neko users can link, served at /link/<name> and /callback/<name>.
+/// Other plugins can add their own through the providers of the Gwaaa module
+pub trait LinkProvider: Send + Sync {
+  fn name(&self) -> &'static str;
+
+  /// Whether the provider takes a PKCE challenge along with the state
+  fn pkce(&self) -> bool {
+    true
+  }
+
+  /// Whether linking logs in, making a neko user if the account isn't linked yet
+  fn login(&self) -> bool {
+    false
+  }
+
+  fn storage(&self) -> Storage;
+
+  /// Where to send the user to, the authorization has to be passed along
+  fn authorize_url(&self, redirect_uri: &str, auth: &Authorization) -> String;
+
+  /// State the provider sent back
+  fn state<'a>(&self, params: &'a Params, _redirect_uri: &str) -> Option<&'a str> {
+    params.get("state").map(String::as_str)
+  }
+
+  /// Trade the callback for an access token, or anything else identity can tell the account from
+  fn exchange<'a>(
+    &'a self,
+    params: &'a Params,
+    redirect_uri: &'a str,
+    verifier: Option<&'a str>,
+  ) -> BoxFuture<'a, Res<String>>;
+
+  /// Id of the account, as stored in the account column
+  fn identity<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Res<Value>>;
+
+  /// Runs after the account got linked
+  fn linked<'a>(&'a self, _neko_id: i32, _account: &'a Value) -> BoxFuture<'a, R> {
+    Box::pin(async { Ok(()) })
+  }
+}
+
+// Filled once all plugins had the chance to add theirs
+static PROVIDERS: OnceCell<HashMap<&'static str, Box<dyn LinkProvider>>> = OnceCell::const_new();
+
+pub fn set_providers(providers: Vec<Box<dyn LinkProvider>>) {
+  let _ = PROVIDERS.set(providers.into_iter().map(|p| (p.name(), p)).collect());
+}
+
+fn provider(name: &str) -> Option<&'static dyn LinkProvider> {
+  PROVIDERS.get()?.get(name).map(|p| p.as_ref())
+}
+
+async fn redirect_uri(p: &dyn LinkProvider) -> String {
+  format!("{}/callback/{}", root_domain().await, p.name())
+}
+
+fn not_found() -> Response {
+  error_page(StatusCode::NOT_FOUND, "there's nothing to link here")
+}
+
+pub async fn link(session: SessionPgSession, Path(name): Path<String>) -> Response {
+  let Some(p) = provider(&name) else {
+    return not_found();
+  };
+  if !p.login() && session.get::<i32>("neko_id").is_none() {
+    return Redirect::to("/login").into_response();
+  }
+  let auth = begin(&session, p.name(), p.pkce());
+  Redirect::to(&p.authorize_url(&redirect_uri(p).await, &auth)).into_response()
+}
+
+pub async fn callback(
+  session: SessionPgSession,
+  Path(name): Path<String>,
+  Form(params): Form<Params>,
+) -> Response {
+  let Some(p) = provider(&name) else {
+    return not_found();
+  };
+  let neko_id = session.get::<i32>("neko_id");
+  if !p.login() && neko_id.is_none() {
+    return Redirect::to("/login").into_response();
+  }
+  let redirect_uri = redirect_uri(p).await;
+  let Some(state) = p.state(&params, &redirect_uri) else {
+    return error_page(
+      StatusCode::BAD_REQUEST,
+      "this link wasn't started by you, please try again",
+    );
+  };
+  let verifier = match verify(&session, p.name(), state) {
+    Ok(verifier) => verifier,
+    Err(msg) => return error_page(StatusCode::BAD_REQUEST, msg),
+  };
+
+  let account = match authorize(p, &params, &redirect_uri, verifier.as_deref()).await {
+    Ok(account) => account,
+    Err(err) => {
+      if let Some(Rejected(message)) = err.downcast_ref::<Rejected>() {
+        return error_page(StatusCode::BAD_REQUEST, message);
+      }
+      log::warn!("Failed to authorize {name} account: {err}");
+      return error_page(
+        StatusCode::BAD_GATEWAY,
+        &format!("{name} didn't accept the login, please try again"),
+      );
+    }
+  };
+  let id = match store(p, neko_id, account.clone()).await {
+    Ok(id) => id,
+    Err(err) => {
+      log::error!("Failed to link {name} account: {err}");
+      return error_page(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "couldn't link the account, please try again later",
+      );
+    }
+  };
+  if neko_id.is_none() {
+    session.set("neko_id", id);
+  }
+  if let Err(err) = p.linked(id, &account).await {
+    log::warn!("Failed to finish linking {name} account of neko user {id}: {err}");
+  }
+  Redirect::to("/").into_response()
+}
+
+async fn authorize(
+  p: &dyn LinkProvider,
+  params: &Params,
+  redirect_uri: &str,
+  verifier: Option<&str>,
+) -> Res<Value> {
+  let token = p.exchange(params, redirect_uri, verifier).await?;
+  p.identity(&token).await
+}
+
+// Logging in picks the neko user the account is linked to, or makes a new one
+async fn login(storage: &Storage, account: Value) -> Res<i32> {
+  let mut qb = Query::select();
+  qb.from(storage.table.clone());
+  qb.column(storage.neko_id.clone());
+  qb.and_where(Expr::col(storage.account_id.clone()).eq(account));
+  if let Some((id,)) = fetch_optional!(&qb, (i32,))? {
+    return Ok(id);
+  }
+  use crate::plugins::neko::schema::Users::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([Slug]);
+  qb.values([Option::<String>::None.into()])?;
+  qb.returning(Query::returning().columns([Id]));
+  Ok(fetch_one!(&qb, (i32,))?.0)
+}
+
+async fn store(p: &dyn LinkProvider, neko_id: Option<i32>, account: Value) -> Res<i32> {
+  let storage = p.storage();
+  let id = match neko_id {
+    Some(id) => id,
+    None => login(&storage, account.clone()).await?,
+  };
+  let mut qb = Query::insert();
+  qb.into_table(storage.table);
+  qb.columns([storage.neko_id, storage.account_id]);
+  qb.values([id.into(), account.into()])?;
+  qb.on_conflict(storage.on_conflict);
+  execute!(&qb)?;
+  Ok(id)
+}
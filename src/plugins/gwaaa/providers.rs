@@ -0,0 +1,504 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::{
+  oauth::{use_nonce, Authorization},
+  provider::{LinkProvider, OAuthClient, Params, Rejected, Storage, TokenRes},
+  UsersMinecraft,
+};
+use crate::{
+  core::*,
+  modules::reqwest::req,
+  plugins::{
+    neko::schema::{UsersAnilist, UsersDiscord, UsersGithub, UsersSteam},
+    steam::{
+      query::{update_playdata, update_users},
+      sync::sync_neko_user,
+    },
+  },
+};
+use futures::future::BoxFuture;
+use poise::serenity_prelude::json::json;
+use regex::Regex;
+use sea_query::{IntoIden, OnConflict, Value};
+use url::Url;
+use urlencoding::encode;
+use uuid::Uuid;
+
+/// Providers that come with gwaaa
+pub fn builtin() -> Vec<Box<dyn LinkProvider>> {
+  vec![
+    Box::new(Discord {
+      client: OAuthClient {
+        id: expect_env!("OAUTH_DISCORD_ID"),
+        secret: expect_env!("OAUTH_DISCORD_SECRET"),
+      },
+    }),
+    Box::new(Steam),
+    Box::new(Github {
+      client: OAuthClient {
+        id: expect_env!("OAUTH_GITHUB_ID"),
+        secret: expect_env!("OAUTH_GITHUB_SECRET"),
+      },
+    }),
+    Box::new(Anilist {
+      client: OAuthClient {
+        id: expect_env!("OAUTH_ANILIST_ID"),
+        secret: expect_env!("OAUTH_ANILIST_SECRET"),
+      },
+    }),
+    Box::new(Minecraft {
+      client: OAuthClient {
+        id: expect_env!("OAUTH_MINECRAFT_ID"),
+        secret: expect_env!("OAUTH_MINECRAFT_SECRET"),
+      },
+    }),
+  ]
+}
+
+fn sync<'a>(neko_id: i32) -> BoxFuture<'a, R> {
+  Box::pin(sync_neko_user(neko_id))
+}
+
+struct Discord {
+  client: OAuthClient,
+}
+
+#[derive(serde::Deserialize)]
+struct DiscordAuthRes {
+  user: Option<DiscordUser>,
+}
+
+#[derive(serde::Deserialize)]
+struct DiscordUser {
+  id: String,
+}
+
+impl LinkProvider for Discord {
+  fn name(&self) -> &'static str {
+    "discord"
+  }
+
+  fn login(&self) -> bool {
+    true
+  }
+
+  fn storage(&self) -> Storage {
+    use UsersDiscord::*;
+    Storage {
+      table: Table.into_iden(),
+      neko_id: NekoId.into_iden(),
+      account_id: DiscordId.into_iden(),
+      on_conflict: OnConflict::column(DiscordId)
+        .update_column(NekoId)
+        .to_owned(),
+    }
+  }
+
+  fn authorize_url(&self, redirect_uri: &str, auth: &Authorization) -> String {
+    format!(
+      "https://discord.com/oauth2/authorize\
+      ?client_id={}&redirect_uri={}&response_type=code\
+      &scope=identify&prompt=consent{}",
+      self.client.id,
+      encode(redirect_uri),
+      auth.query()
+    )
+  }
+
+  fn exchange<'a>(
+    &'a self,
+    params: &'a Params,
+    redirect_uri: &'a str,
+    verifier: Option<&'a str>,
+  ) -> BoxFuture<'a, Res<String>> {
+    Box::pin(async move {
+      let res = req()
+        .post("https://discord.com/api/v10/oauth2/token")
+        .form(&self.client.token_req(params, redirect_uri, verifier)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenRes>()
+        .await?;
+      Ok(res.access_token)
+    })
+  }
+
+  fn identity<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Res<Value>> {
+    Box::pin(async move {
+      let res = req()
+        .get("https://discord.com/api/v10/oauth2/@me")
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<DiscordAuthRes>()
+        .await?;
+      let user = res.user.ok_or("Discord returned no user")?;
+      Ok(user.id.parse::<i64>()?.into())
+    })
+  }
+
+  fn linked<'a>(&'a self, neko_id: i32, _: &'a Value) -> BoxFuture<'a, R> {
+    sync(neko_id)
+  }
+}
+
+/// Steam only does OpenID 2.0, the state rides along in return_to
+struct Steam;
+
+const STEAM_OPENID: &str = "https://steamcommunity.com/openid/login";
+
+once_cell!(sid_regex, SID_REGEX: Regex, {
+  Regex::new("^https://steamcommunity.com/openid/id/([0-9]{17})$").unwrap()
+});
+
+#[derive(serde::Serialize)]
+struct RedirectForm<'a> {
+  #[serde(rename = "openid.ns")]
+  ns: &'static str,
+  #[serde(rename = "openid.identity")]
+  identity: &'static str,
+  #[serde(rename = "openid.claimed_id")]
+  claimed_id: &'static str,
+  #[serde(rename = "openid.mode")]
+  mode: &'static str,
+  #[serde(rename = "openid.return_to")]
+  return_to: &'a str,
+  #[serde(rename = "openid.realm")]
+  realm: &'a str,
+}
+
+impl LinkProvider for Steam {
+  fn name(&self) -> &'static str {
+    "steam"
+  }
+
+  fn pkce(&self) -> bool {
+    false
+  }
+
+  fn storage(&self) -> Storage {
+    use UsersSteam::*;
+    Storage {
+      table: Table.into_iden(),
+      neko_id: NekoId.into_iden(),
+      account_id: SteamId.into_iden(),
+      on_conflict: OnConflict::column(SteamId).update_column(NekoId).to_owned(),
+    }
+  }
+
+  fn authorize_url(&self, redirect_uri: &str, auth: &Authorization) -> String {
+    let realm =
+      Url::parse(redirect_uri).map_or(String::new(), |u| u.origin().ascii_serialization());
+    let form = RedirectForm {
+      ns: "http://specs.openid.net/auth/2.0",
+      identity: "http://specs.openid.net/auth/2.0/identifier_select",
+      claimed_id: "http://specs.openid.net/auth/2.0/identifier_select",
+      mode: "checkid_setup",
+      realm: &realm,
+      return_to: &format!("{redirect_uri}?state={}", auth.state),
+    };
+    format!(
+      "{STEAM_OPENID}?{}",
+      serde_urlencoded::to_string(&form).unwrap_or_default()
+    )
+  }
+
+  // Only trust the state if steam sent the user back to our callback
+  fn state<'a>(&self, params: &'a Params, redirect_uri: &str) -> Option<&'a str> {
+    let return_to = params.get("openid.return_to")?;
+    let (base, query) = return_to.split_once('?')?;
+    if base != redirect_uri {
+      return None;
+    }
+    query.strip_prefix("state=")
+  }
+
+  // The verified claimed id stands in for the token
+  fn exchange<'a>(
+    &'a self,
+    params: &'a Params,
+    _: &'a str,
+    _: Option<&'a str>,
+  ) -> BoxFuture<'a, Res<String>> {
+    Box::pin(async move {
+      let param = |key: &str| params.get(key).map_or("", String::as_str);
+      // Both have to be covered by the signature, or they could be swapped out
+      let signed: Vec<_> = param("openid.signed").split(',').collect();
+      if param("openid.op_endpoint") != STEAM_OPENID
+        || !signed.contains(&"return_to")
+        || !signed.contains(&"response_nonce")
+      {
+        return Err(Rejected("this doesn't look like a steam login, please try again").into());
+      }
+      let mut validate = params.clone();
+      validate.insert("openid.mode".into(), "check_authentication".into());
+      let res = req()
+        .post(STEAM_OPENID)
+        .form(&validate)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+      if !res.split('\n').any(|line| line == "is_valid:true") {
+        return Err(Rejected("steam didn't accept the login, please try again").into());
+      }
+      if !use_nonce(param("openid.response_nonce")).await? {
+        return Err(Rejected("this login was already used, please try again").into());
+      }
+      Ok(param("openid.claimed_id").to_string())
+    })
+  }
+
+  fn identity<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Res<Value>> {
+    Box::pin(async move {
+      let captures = (sid_regex().await)
+        .captures(token)
+        .ok_or("Steam returned an invalid claimed id")?;
+      Ok(captures[1].parse::<i64>()?.into())
+    })
+  }
+
+  fn linked<'a>(&'a self, neko_id: i32, account: &'a Value) -> BoxFuture<'a, R> {
+    Box::pin(async move {
+      if let Value::BigInt(Some(steam_id)) = account {
+        let c = vec![(*steam_id,)];
+        update_users(&c).await?;
+        update_playdata(&c).await?;
+      }
+      sync_neko_user(neko_id).await
+    })
+  }
+}
+
+/// GitHub apps only take a state, not a PKCE challenge
+struct Github {
+  client: OAuthClient,
+}
+
+#[derive(serde::Deserialize)]
+struct GithubUser {
+  id: i64,
+}
+
+impl LinkProvider for Github {
+  fn name(&self) -> &'static str {
+    "github"
+  }
+
+  fn pkce(&self) -> bool {
+    false
+  }
+
+  fn storage(&self) -> Storage {
+    use UsersGithub::*;
+    Storage {
+      table: Table.into_iden(),
+      neko_id: NekoId.into_iden(),
+      account_id: GithubId.into_iden(),
+      on_conflict: OnConflict::column(GithubId).do_nothing().to_owned(),
+    }
+  }
+
+  fn authorize_url(&self, redirect_uri: &str, auth: &Authorization) -> String {
+    format!(
+      "https://github.com/login/oauth/authorize\
+      ?client_id={}&redirect_uri={}&response_type=code\
+      &allow_signup=false{}",
+      self.client.id,
+      encode(redirect_uri),
+      auth.query()
+    )
+  }
+
+  fn exchange<'a>(
+    &'a self,
+    params: &'a Params,
+    redirect_uri: &'a str,
+    verifier: Option<&'a str>,
+  ) -> BoxFuture<'a, Res<String>> {
+    Box::pin(async move {
+      let res = req()
+        .post("https://github.com/login/oauth/access_token")
+        .header("Accept", "application/json")
+        .form(&self.client.token_req(params, redirect_uri, verifier)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenRes>()
+        .await?;
+      Ok(res.access_token)
+    })
+  }
+
+  fn identity<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Res<Value>> {
+    Box::pin(async move {
+      let res = req()
+        .get("https://api.github.com/user")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header("Accept", "application/vnd.github+json")
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<GithubUser>()
+        .await?;
+      Ok(res.id.into())
+    })
+  }
+}
+
+struct Anilist {
+  client: OAuthClient,
+}
+
+#[derive(serde::Deserialize)]
+struct AnilistRes {
+  data: AnilistData,
+}
+
+#[derive(serde::Deserialize)]
+struct AnilistData {
+  #[serde(rename = "Viewer")]
+  viewer: AnilistViewer,
+}
+
+#[derive(serde::Deserialize)]
+struct AnilistViewer {
+  id: i64,
+}
+
+impl LinkProvider for Anilist {
+  fn name(&self) -> &'static str {
+    "anilist"
+  }
+
+  fn storage(&self) -> Storage {
+    use UsersAnilist::*;
+    Storage {
+      table: Table.into_iden(),
+      neko_id: NekoId.into_iden(),
+      account_id: AnilistId.into_iden(),
+      on_conflict: OnConflict::column(AnilistId).do_nothing().to_owned(),
+    }
+  }
+
+  fn authorize_url(&self, redirect_uri: &str, auth: &Authorization) -> String {
+    format!(
+      "https://anilist.co/api/v2/oauth/authorize\
+      ?client_id={}&redirect_uri={}&response_type=code{}",
+      self.client.id,
+      encode(redirect_uri),
+      auth.query()
+    )
+  }
+
+  fn exchange<'a>(
+    &'a self,
+    params: &'a Params,
+    redirect_uri: &'a str,
+    verifier: Option<&'a str>,
+  ) -> BoxFuture<'a, Res<String>> {
+    Box::pin(async move {
+      let res = req()
+        .post("https://anilist.co/api/v2/oauth/token")
+        .header("Accept", "application/json")
+        .json(&self.client.token_req(params, redirect_uri, verifier)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenRes>()
+        .await?;
+      Ok(res.access_token)
+    })
+  }
+
+  fn identity<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Res<Value>> {
+    Box::pin(async move {
+      let res = req()
+        .post("https://graphql.anilist.co")
+        .header("Accept", "application/json")
+        .bearer_auth(token)
+        .json(&json!({ "query": "{Viewer{id}}" }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<AnilistRes>()
+        .await?;
+      Ok(res.data.viewer.id.into())
+    })
+  }
+}
+
+/// Minecraft accounts go through mc-auth.com, a neko user can only link one
+struct Minecraft {
+  client: OAuthClient,
+}
+
+#[derive(serde::Deserialize)]
+struct MinecraftTokenRes {
+  data: MinecraftProfile,
+}
+
+#[derive(serde::Deserialize)]
+struct MinecraftProfile {
+  uuid: Uuid,
+}
+
+impl LinkProvider for Minecraft {
+  fn name(&self) -> &'static str {
+    "minecraft"
+  }
+
+  fn storage(&self) -> Storage {
+    use UsersMinecraft::*;
+    Storage {
+      table: Table.into_iden(),
+      neko_id: NekoId.into_iden(),
+      account_id: McUuid.into_iden(),
+      on_conflict: OnConflict::column(NekoId).do_nothing().to_owned(),
+    }
+  }
+
+  fn authorize_url(&self, redirect_uri: &str, auth: &Authorization) -> String {
+    format!(
+      "https://mc-auth.com/oAuth2/authorize\
+      ?client_id={}&redirect_uri={}&response_type=code&scope=profile{}",
+      self.client.id,
+      encode(redirect_uri),
+      auth.query()
+    )
+  }
+
+  // The token response already carries the profile, so its uuid stands in for the token
+  fn exchange<'a>(
+    &'a self,
+    params: &'a Params,
+    redirect_uri: &'a str,
+    verifier: Option<&'a str>,
+  ) -> BoxFuture<'a, Res<String>> {
+    Box::pin(async move {
+      let res = req()
+        .post("https://mc-auth.com/oAuth2/token")
+        .json(&self.client.token_req(params, redirect_uri, verifier)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<MinecraftTokenRes>()
+        .await?;
+      Ok(res.data.uuid.to_string())
+    })
+  }
+
+  fn identity<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Res<Value>> {
+    Box::pin(async move { Ok(Uuid::parse_str(token)?.into()) })
+  }
+
+  fn linked<'a>(&'a self, neko_id: i32, _: &'a Value) -> BoxFuture<'a, R> {
+    sync(neko_id)
+  }
+}
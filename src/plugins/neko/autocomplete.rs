@@ -9,6 +9,9 @@ use sea_query::{Alias, Expr, Func, SelectStatement, Order};
 
 macro_rules! autocomplete {
   ( $fn_name:ident, $path:path) => {
+    autocomplete!($fn_name, $path, |_| {});
+  };
+  ( $fn_name:ident, $path:path, $filter:expr) => {
     pub async fn $fn_name<'a>(_: Ctx<'_>, search: &'a str) -> Vec<AutocompleteChoice<String>> {
       use $path::*;
       let mut qb = SelectStatement::new();
@@ -23,6 +26,7 @@ macro_rules! autocomplete {
               .like(format!("%{search}%")),
           ),
       );
+      ($filter)(&mut qb);
       qb.order_by(Name, Order::Asc);
       qb.limit(25);
       use unicode_truncate::UnicodeTruncateStr;
@@ -39,5 +43,9 @@ macro_rules! autocomplete {
 }
 
 autocomplete!(discord_guilds, discord::schema::Guilds);
-autocomplete!(steam_apps, steam::schema::Apps);
+// Only games, apps only known from playdata don't have a type until the next catalogue sync
+autocomplete!(steam_apps, steam::schema::Apps, |qb: &mut SelectStatement| {
+  use steam::schema::Apps::AppType;
+  qb.and_where(Expr::col(AppType).eq("game").or(Expr::col(AppType).is_null()));
+});
 
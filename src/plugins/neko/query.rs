@@ -4,7 +4,7 @@
 
 use super::schema::*;
 use crate::core::*;
-use sea_query::Query;
+use sea_query::{Alias, Expr, Iden, Query, SelectStatement, UnionType};
 
 pub async fn all_steam_connections() -> Res<Vec<(i64,)>> {
   let mut qb = Query::select();
@@ -12,3 +12,96 @@ pub async fn all_steam_connections() -> Res<Vec<(i64,)>> {
   qb.column(UsersSteam::SteamId);
   Ok(fetch_all!(&qb, (i64,))?)
 }
+
+pub async fn discord_connections(neko_id: i32) -> Res<Vec<(i64,)>> {
+  let mut qb = Query::select();
+  qb.from(UsersDiscord::Table);
+  qb.column(UsersDiscord::DiscordId);
+  qb.and_where(Expr::col(UsersDiscord::NekoId).eq(neko_id));
+  Ok(fetch_all!(&qb, (i64,))?)
+}
+
+pub async fn neko_id(discord_id: i64) -> Res<Option<i32>> {
+  let mut qb = Query::select();
+  qb.from(UsersDiscord::Table);
+  qb.column(UsersDiscord::NekoId);
+  qb.and_where(Expr::col(UsersDiscord::DiscordId).eq(discord_id));
+  Ok(fetch_optional!(&qb, (i32,))?.map(|r| r.0))
+}
+
+/// Every account linked to the neko user, as (provider, id) pairs
+pub async fn linked_accounts(neko_id: i32) -> Res<Vec<(String, String)>> {
+  use crate::plugins::gwaaa::UsersMinecraft;
+  fn select<T: Iden + 'static, C: Iden + 'static, N: Iden + 'static>(
+    provider: &str,
+    table: T,
+    id: C,
+    neko: N,
+    neko_id: i32,
+  ) -> SelectStatement {
+    let mut qb = Query::select();
+    qb.from(table);
+    qb.expr(Expr::val(provider).cast_as(Alias::new("TEXT")));
+    qb.expr(Expr::col(id).cast_as(Alias::new("TEXT")));
+    qb.and_where(Expr::col(neko).eq(neko_id));
+    qb
+  }
+  let mut qb = select(
+    "discord",
+    UsersDiscord::Table,
+    UsersDiscord::DiscordId,
+    UsersDiscord::NekoId,
+    neko_id,
+  );
+  qb.union(
+    UnionType::All,
+    select(
+      "steam",
+      UsersSteam::Table,
+      UsersSteam::SteamId,
+      UsersSteam::NekoId,
+      neko_id,
+    ),
+  );
+  qb.union(
+    UnionType::All,
+    select(
+      "github",
+      UsersGithub::Table,
+      UsersGithub::GithubId,
+      UsersGithub::NekoId,
+      neko_id,
+    ),
+  );
+  qb.union(
+    UnionType::All,
+    select(
+      "anilist",
+      UsersAnilist::Table,
+      UsersAnilist::AnilistId,
+      UsersAnilist::NekoId,
+      neko_id,
+    ),
+  );
+  qb.union(
+    UnionType::All,
+    select(
+      "telegram",
+      UsersTelegram::Table,
+      UsersTelegram::TelegramId,
+      UsersTelegram::NekoId,
+      neko_id,
+    ),
+  );
+  qb.union(
+    UnionType::All,
+    select(
+      "minecraft",
+      UsersMinecraft::Table,
+      UsersMinecraft::McUuid,
+      UsersMinecraft::NekoId,
+      neko_id,
+    ),
+  );
+  Ok(fetch_all!(&qb, (String, String))?)
+}
@@ -0,0 +1,96 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::{
+  core::*,
+  modules::resvg::{escape, render_png, Theme},
+};
+
+/// Summary of a single linked account
+pub struct Section {
+  pub provider: &'static str,
+  pub title: String,
+  pub url: Option<String>,
+  pub stats: Vec<(&'static str, String)>,
+}
+
+const WIDTH: f64 = 800.0;
+const MARGIN: f64 = 24.0;
+const HEADER: f64 = 88.0;
+const LINE: f64 = 22.0;
+const COLUMNS: usize = 2;
+
+/// Profile card, sections are laid out in columns, each going to the shortest one
+pub struct Card {
+  pub theme: Theme,
+  pub name: String,
+  pub sections: Vec<Section>,
+}
+
+impl Card {
+  fn section_height(s: &Section) -> f64 {
+    // Provider, title, then a line per stat
+    16.0 + LINE * (2 + s.stats.len()) as f64 + 8.0
+  }
+
+  pub fn to_svg(&self) -> String {
+    let p = self.theme.palette();
+    let column = (WIDTH - MARGIN * (COLUMNS + 1) as f64) / COLUMNS as f64;
+    let mut heights = [HEADER; COLUMNS];
+    let mut boxes = String::new();
+    for s in &self.sections {
+      let (i, _) = (heights.iter().enumerate())
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap_or((0, &HEADER));
+      let (x, y, h) = (
+        MARGIN + (column + MARGIN) * i as f64,
+        heights[i],
+        Self::section_height(s),
+      );
+      boxes += &format!(
+        "<rect x=\"{x}\" y=\"{y}\" width=\"{column}\" height=\"{h}\" rx=\"8\" fill=\"none\" stroke=\"{}\"/>\
+        <text x=\"{}\" y=\"{}\" font-size=\"13\" fill=\"{}\" font-weight=\"bold\">{}</text>\
+        <text x=\"{}\" y=\"{}\" font-size=\"18\">{}</text>",
+        p.grid,
+        x + 16.0,
+        y + 8.0 + LINE,
+        p.accent,
+        escape(&s.provider.to_uppercase()),
+        x + 16.0,
+        y + 8.0 + LINE * 2.0,
+        escape(&s.title)
+      );
+      for (n, (label, value)) in s.stats.iter().enumerate() {
+        let line = y + 8.0 + LINE * (3 + n) as f64;
+        boxes += &format!(
+          "<text x=\"{}\" y=\"{line}\" font-size=\"14\" fill=\"{}\">{}</text>\
+          <text x=\"{}\" y=\"{line}\" font-size=\"14\" text-anchor=\"end\">{}</text>",
+          x + 16.0,
+          p.grid,
+          escape(label),
+          x + column - 16.0,
+          escape(value)
+        );
+      }
+      heights[i] += h + MARGIN;
+    }
+    let height = heights.iter().fold(HEADER + MARGIN, |a, b| a.max(*b));
+    format!(
+      "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{height}\" \
+      font-family=\"sans-serif\" fill=\"{}\">\
+      <rect width=\"{WIDTH}\" height=\"{height}\" fill=\"{}\"/>\
+      <rect width=\"{WIDTH}\" height=\"6\" fill=\"{}\"/>\
+      <text x=\"{MARGIN}\" y=\"52\" font-size=\"30\" font-weight=\"bold\">{}</text>\
+      {boxes}</svg>",
+      p.foreground,
+      p.background,
+      p.accent,
+      escape(&self.name)
+    )
+  }
+
+  pub fn render(&self) -> Res<Vec<u8>> {
+    render_png(&self.to_svg())
+  }
+}
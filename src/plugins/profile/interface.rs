@@ -0,0 +1,88 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use crate::{core::*, modules::reqwest::req};
+use poise::serenity_prelude::json::json;
+use serde::Deserialize;
+
+const ANILIST_USER: &str = "query ($id: Int) { User(id: $id) { name siteUrl statistics { \
+  anime { count minutesWatched } manga { count chaptersRead } } } }";
+
+pub async fn get_anilist_user(id: i64) -> Res<AnilistUser> {
+  let res = req()
+    .post("https://graphql.anilist.co")
+    .header("Content-Type", "application/json")
+    .header("Accept", "application/json")
+    .json(&json!({
+      "query": ANILIST_USER,
+      "variables": { "id": id }
+    }))
+    .send()
+    .await?
+    .error_for_status()?
+    .json::<AnilistRes>()
+    .await?;
+  Ok(res.data.user)
+}
+
+#[derive(Deserialize)]
+struct AnilistRes {
+  data: AnilistData,
+}
+
+#[derive(Deserialize)]
+struct AnilistData {
+  #[serde(rename = "User")]
+  user: AnilistUser,
+}
+
+#[derive(Deserialize)]
+pub struct AnilistUser {
+  pub name: String,
+  #[serde(rename = "siteUrl")]
+  pub site_url: String,
+  pub statistics: AnilistStatistics,
+}
+
+#[derive(Deserialize)]
+pub struct AnilistStatistics {
+  pub anime: AnimeStatistics,
+  pub manga: MangaStatistics,
+}
+
+#[derive(Deserialize)]
+pub struct AnimeStatistics {
+  pub count: i64,
+  #[serde(rename = "minutesWatched")]
+  pub minutes_watched: i64,
+}
+
+#[derive(Deserialize)]
+pub struct MangaStatistics {
+  pub count: i64,
+  #[serde(rename = "chaptersRead")]
+  pub chapters_read: i64,
+}
+
+pub async fn get_github_user(id: i64) -> Res<GithubUser> {
+  Ok(
+    req()
+      .get(format!("https://api.github.com/user/{id}"))
+      .header("X-GitHub-Api-Version", "2022-11-28")
+      .header("Accept", "application/vnd.github+json")
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<GithubUser>()
+      .await?,
+  )
+}
+
+#[derive(Deserialize)]
+pub struct GithubUser {
+  pub login: String,
+  pub html_url: String,
+  pub public_repos: i64,
+  pub followers: i64,
+}
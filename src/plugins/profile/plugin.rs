@@ -0,0 +1,240 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use self::{
+  card::{Card, Section},
+  interface::{get_anilist_user, get_github_user},
+};
+use crate::{
+  core::{time::fmt_sec, *},
+  modules::{
+    poise::{Ctx, Poise},
+    resvg::{Resvg, Theme},
+    sqlx::Postgres,
+  },
+  plugins::{
+    beatleader::beatleader_rank,
+    discord::voice::voice_total,
+    neko::query::{linked_accounts, neko_id},
+    steam::query::{steam_accounts, steam_totals},
+  },
+};
+use poise::serenity_prelude::{AttachmentType, UserId};
+
+pub mod card;
+pub mod interface;
+
+/// Profile module, shows every account linked to a neko user in one place
+pub struct Profile;
+
+impl Module for Profile {
+  async fn init(&mut self, fw: &mut Framework) -> R {
+    fw.req_module::<Postgres>().await?;
+    fw.req_module::<Resvg>().await?;
+    let poise = fw.req_module::<Poise>().await?;
+    poise.commands.push(profile());
+    Ok(())
+  }
+}
+
+async fn discord_section(ctx: Ctx<'_>, id: &str) -> Res<Section> {
+  let user = UserId(id.parse()?);
+  let name = match user.to_user(ctx).await {
+    Ok(user) => user.name,
+    Err(_) => id.to_string(),
+  };
+  let mut stats = vec![];
+  let voice = voice_total(user, ctx.guild_id()).await?;
+  if voice > 0 {
+    stats.push(("Voice", fmt_sec(voice)));
+  }
+  Ok(Section {
+    provider: "discord",
+    title: name,
+    url: Some(format!("https://discord.com/users/{id}")),
+    stats,
+  })
+}
+
+// All linked Steam accounts are summed up, the most played one is shown
+async fn steam_sections(discord_id: i64, neko: i32) -> Res<Vec<Section>> {
+  let accounts = steam_accounts(discord_id).await?;
+  let Some(main) = accounts.first() else {
+    return Ok(vec![]);
+  };
+  let (playtime, owned) = steam_totals(neko).await?;
+  let mut stats = vec![
+    ("Playtime", fmt_sec(playtime * 60)),
+    ("Games", owned.to_string()),
+  ];
+  if accounts.len() > 1 {
+    stats.push(("Accounts", accounts.len().to_string()));
+  }
+  let mut sections = vec![Section {
+    provider: "steam",
+    title: main.name.clone().unwrap_or(main.id.to_string()),
+    url: main.profile_url.clone(),
+    stats,
+  }];
+  let ids = accounts.iter().map(|a| a.id).collect();
+  if let Some((id, pp, rank)) = beatleader_rank(ids).await? {
+    sections.push(Section {
+      provider: "beatleader",
+      title: (accounts.iter())
+        .find(|a| a.id == id)
+        .and_then(|a| a.name.clone())
+        .unwrap_or(id.to_string()),
+      url: Some(format!("https://beatleader.xyz/u/{id}")),
+      stats: vec![("PP", format!("{pp:.2}")), ("Rank", format!("#{rank}"))],
+    });
+  }
+  Ok(sections)
+}
+
+async fn anilist_section(id: &str) -> Res<Section> {
+  let user = get_anilist_user(id.parse()?).await?;
+  let stats = user.statistics;
+  Ok(Section {
+    provider: "anilist",
+    title: user.name,
+    url: Some(user.site_url),
+    stats: vec![
+      ("Anime", stats.anime.count.to_string()),
+      ("Watched", fmt_sec(stats.anime.minutes_watched * 60)),
+      ("Manga", stats.manga.count.to_string()),
+      ("Chapters", stats.manga.chapters_read.to_string()),
+    ],
+  })
+}
+
+async fn github_section(id: &str) -> Res<Section> {
+  let user = get_github_user(id.parse()?).await?;
+  Ok(Section {
+    provider: "github",
+    title: user.login,
+    url: Some(user.html_url),
+    stats: vec![
+      ("Repositories", user.public_repos.to_string()),
+      ("Followers", user.followers.to_string()),
+    ],
+  })
+}
+
+const PROVIDERS: [&str; 6] = [
+  "discord",
+  "steam",
+  "anilist",
+  "github",
+  "telegram",
+  "minecraft",
+];
+
+// Accounts whose provider couldn't be reached are still listed
+fn plain_section(provider: &'static str, id: &str) -> Section {
+  let url = match provider {
+    "minecraft" => Some(format!("https://namemc.com/profile/{id}")),
+    _ => None,
+  };
+  Section {
+    provider,
+    title: id.to_string(),
+    url,
+    stats: vec![],
+  }
+}
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn profile(ctx: Ctx<'_>, user: Option<UserId>, theme: Option<Theme>) -> R {
+  let user = user.unwrap_or(ctx.author().id);
+  let discord_id = user.0 as i64;
+  let Some(neko) = neko_id(discord_id).await? else {
+    ctx
+      .reply("This user has no linked accounts, link some at <https://link.neko.rs>")
+      .await?;
+    return Ok(());
+  };
+  ctx.defer().await?;
+
+  let mut sections = vec![];
+  let mut steam = false;
+  for (provider, id) in linked_accounts(neko).await? {
+    let Some(provider) = PROVIDERS.into_iter().find(|p| *p == provider) else {
+      continue;
+    };
+    let section = match provider {
+      "discord" => discord_section(ctx, &id).await.map(|s| vec![s]),
+      // Every Steam account is summarized at once
+      "steam" if steam => continue,
+      "steam" => {
+        steam = true;
+        steam_sections(discord_id, neko).await
+      }
+      "anilist" => anilist_section(&id).await.map(|s| vec![s]),
+      "github" => github_section(&id).await.map(|s| vec![s]),
+      _ => Ok(vec![plain_section(provider, &id)]),
+    };
+    match section {
+      Ok(section) => sections.extend(section),
+      Err(err) => {
+        log::warn!("Failed to summarize {provider} account {id}: {err}");
+        sections.push(plain_section(provider, &id));
+      }
+    }
+  }
+
+  let user = user.to_user(ctx).await?;
+  let name = match ctx.guild_id() {
+    Some(guild) => user.nick_in(ctx, guild).await,
+    None => None,
+  }
+  .unwrap_or(user.name.clone());
+  let card = Card {
+    theme: theme.unwrap_or(Theme::Dark),
+    name: name.clone(),
+    sections,
+  };
+  let links: Vec<_> = (card.sections.iter())
+    .filter_map(|s| Some(format!("[{}]({})", s.provider, s.url.as_ref()?)))
+    .collect();
+  match card.render() {
+    Ok(png) => {
+      ctx
+        .send(|b| {
+          b.embed(|e| {
+            e.title(format!("{name}'s profile"))
+              .description(links.join(" · "))
+              .image("attachment://profile.png")
+          })
+          .attachment(AttachmentType::Bytes {
+            data: png.into(),
+            filename: "profile.png".into(),
+          })
+        })
+        .await?;
+    }
+    // Same information as the card, as embed fields
+    Err(err) => {
+      log::warn!("Failed to render the profile card of {user}: {err}");
+      ctx
+        .send(|b| {
+          b.embed(|e| {
+            e.title(format!("{name}'s profile"));
+            for s in &card.sections {
+              let mut value = match &s.url {
+                Some(url) => format!("[{}]({url})", s.title),
+                None => s.title.clone(),
+              };
+              for (label, stat) in &s.stats {
+                value += &format!("\n{label}: {stat}");
+              }
+              e.field(s.provider, value, true);
+            }
+            e
+          })
+        })
+        .await?;
+    }
+  }
+  Ok(())
+}
@@ -3,26 +3,124 @@
 // This project is dual licensed under MIT and Apache.
 
 use serde::Deserialize;
+use std::collections::HashMap;
 
-api!(ISteamApps, "https://api.steampowered.com/ISteamApps/", {
-  fn get_app_list("GetAppList/v2") -> GetAppList;
+api!(IStoreService, "https://api.steampowered.com/IStoreService/", {
+  fn get_store_apps("GetAppList/v1") -> Response<StoreAppList> {
+    key: &str,
+    if_modified_since: i64,
+    include_games: bool,
+    include_dlc: bool,
+    include_software: bool,
+    include_videos: bool,
+    include_hardware: bool,
+    last_appid: u32,
+    max_results: u32,
+  };
 });
 
 #[derive(Deserialize)]
-pub struct GetAppList {
-  pub applist: Applist,
+pub struct StoreAppList {
+  #[serde(default)]
+  pub apps: Vec<StoreApp>,
+  #[serde(default)]
+  pub have_more_results: bool,
+  pub last_appid: Option<u32>,
 }
 
 #[derive(Deserialize)]
-pub struct Applist {
-  pub apps: Vec<App>,
-}
-
-#[derive(Deserialize)]
-pub struct App {
+pub struct StoreApp {
   #[serde(rename = "appid")]
   pub id: u32,
   pub name: String,
+  pub last_modified: i64,
+}
+
+api!(StoreApi, "https://store.steampowered.com/api/", {
+  fn get_app_details("appdetails") -> HashMap<String, AppDetailsResult> {
+    appids: u32,
+    filters: &str,
+  };
+  // Only the price overview can be requested for several apps at once
+  fn get_price_overviews("appdetails") -> HashMap<String, PriceResult> {
+    appids: String,
+    cc: &str,
+    filters: &str,
+  };
+});
+
+#[derive(Deserialize)]
+pub struct PriceResult {
+  pub success: bool,
+  pub data: Option<PriceData>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PriceData {
+  Priced { price_overview: PriceOverview },
+  // Free apps get an empty array instead
+  Free(Vec<serde_json::Value>),
+}
+
+#[derive(Deserialize)]
+pub struct PriceOverview {
+  pub currency: String,
+  // In cents of the currency
+  pub initial: i32,
+  #[serde(rename = "final")]
+  pub price: i32,
+  pub discount_percent: i32,
+  pub initial_formatted: String,
+  pub final_formatted: String,
+}
+
+#[derive(Deserialize)]
+pub struct AppDetailsResult {
+  pub success: bool,
+  pub data: Option<AppDetails>,
+}
+
+#[derive(Deserialize)]
+pub struct AppDetails {
+  pub release_date: Option<ReleaseDate>,
+}
+
+#[derive(Deserialize)]
+pub struct ReleaseDate {
+  pub coming_soon: bool,
+  // Localized and free form, like "21 Aug, 2012" or "Q1 2025"
+  pub date: String,
+}
+
+api!(ISteamNews, "https://api.steampowered.com/ISteamNews/", {
+  fn get_news_for_app("GetNewsForApp/v2") -> GetNewsForApp {
+    appid: u32,
+    count: u32,
+    feeds: &str,
+  };
+});
+
+#[derive(Deserialize)]
+pub struct GetNewsForApp {
+  #[serde(rename = "appnews")]
+  pub news: AppNews,
+}
+
+#[derive(Deserialize)]
+pub struct AppNews {
+  #[serde(rename = "newsitems", default)]
+  pub items: Vec<NewsItem>,
+}
+
+#[derive(Deserialize)]
+pub struct NewsItem {
+  pub gid: String,
+  pub title: String,
+  pub url: String,
+  // BBCode formatted
+  pub contents: String,
+  pub date: i64,
 }
 
 api!(ISteamUser, "https://api.steampowered.com/ISteamUser/", {
@@ -39,7 +137,7 @@ pub struct Response<T> {
 
 #[derive(Deserialize)]
 pub struct GetPlayerSummaries {
-  pub players: Vec<PlayerSummary>
+  pub players: Vec<PlayerSummary>,
 }
 
 #[derive(Deserialize)]
@@ -48,6 +146,15 @@ pub struct PlayerSummary {
   pub id: String,
   #[serde(rename = "personaname")]
   pub name: String,
+  #[serde(rename = "avatarfull")]
+  pub avatar: String,
+  #[serde(rename = "profileurl")]
+  pub profile_url: String,
+  #[serde(rename = "communityvisibilitystate")]
+  pub visibility: i32,
+  // Only present on public profiles
+  #[serde(rename = "lastlogoff")]
+  pub last_logoff: Option<i64>,
 }
 
 api!(IPlayerService, "https://api.steampowered.com/IPlayerService/", {
@@ -58,15 +165,23 @@ api!(IPlayerService, "https://api.steampowered.com/IPlayerService/", {
     include_played_free_games: bool,
     skip_unvetted_apps: bool,
   };
+  fn get_recently_played_games("GetRecentlyPlayedGames/v1") -> Response<GetRecentlyPlayedGames> {
+    key: &str,
+    steamid: u64,
+    count: u32,
+  };
   fn get_steam_level("GetSteamLevel/v1") -> Response<GetSteamLevel> {
-    key: String,
+    key: &str,
     steamid: u64,
   };
 });
 
 #[derive(Deserialize)]
 pub struct GetRecentlyPlayedGames {
-  pub games: Vec<OwnedApp>,
+  // Both are missing if the game details of the profile are private
+  #[serde(alias = "game_count", rename = "total_count")]
+  pub count: Option<u32>,
+  pub games: Option<Vec<OwnedApp>>,
 }
 
 #[derive(Deserialize)]
@@ -80,7 +195,61 @@ pub struct OwnedApp {
 
 #[derive(Deserialize)]
 pub struct GetSteamLevel {
-  pub player_level: u32,
+  // Missing if the profile is private
+  pub player_level: Option<u32>,
+}
+
+api!(ISteamUserStats, "https://api.steampowered.com/ISteamUserStats/", {
+  fn get_player_achievements("GetPlayerAchievements/v1") -> GetPlayerAchievements {
+    key: &str,
+    steamid: u64,
+    appid: u32,
+  };
+  fn get_schema_for_game("GetSchemaForGame/v2") -> GetSchemaForGame {
+    key: &str,
+    appid: u32,
+  };
+});
+
+#[derive(Deserialize)]
+pub struct GetPlayerAchievements {
+  pub playerstats: PlayerStats,
+}
+
+#[derive(Deserialize)]
+pub struct PlayerStats {
+  pub success: bool,
+  #[serde(default)]
+  pub achievements: Vec<PlayerAchievement>,
+}
+
+#[derive(Deserialize)]
+pub struct PlayerAchievement {
+  #[serde(rename = "apiname")]
+  pub name: String,
+  pub achieved: u8,
+}
+
+#[derive(Deserialize)]
+pub struct GetSchemaForGame {
+  pub game: GameSchema,
+}
+
+#[derive(Deserialize)]
+pub struct GameSchema {
+  #[serde(rename = "availableGameStats")]
+  pub stats: Option<GameStats>,
+}
+
+#[derive(Deserialize)]
+pub struct GameStats {
+  #[serde(default)]
+  pub achievements: Vec<SchemaAchievement>,
+}
+
+#[derive(Deserialize)]
+pub struct SchemaAchievement {
+  pub name: String,
 }
 
 //get_owned_games(get, "IPlayerService/GetOwnedGames", Vec<String>);
@@ -0,0 +1,310 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::{
+  query::{build_top_query, At, By, Of, PlaytimeChange, QueryOutput},
+  schema::{AppLeaders, Apps, MilestoneOptout, MilestoneSettings, Playdata},
+  MAX_HOURS,
+};
+use crate::{
+  core::*,
+  modules::poise::Ctx,
+  plugins::{discord::schema::Members, neko::schema::*},
+};
+use poise::serenity_prelude::{self as serenity, Context, GuildChannel};
+use sea_query::{Expr, Func, OnConflict, Query};
+use sqlx::FromRow;
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_HOURS: &str = "100,500,1000";
+const DEFAULT_GAMES: i32 = 1000;
+
+#[derive(FromRow)]
+struct Recipient {
+  steam_id: i64,
+  discord_id: i64,
+  guild_id: i64,
+  channel_id: i64,
+  hours: String,
+  games: i32,
+  leaders: bool,
+}
+
+// Members of guilds with milestones set up that own the accounts, and didn't opt out
+async fn recipients(steam_ids: Vec<i64>) -> Res<Vec<Recipient>> {
+  let mut optout = Query::select();
+  optout.from(MilestoneOptout::Table);
+  optout.column(MilestoneOptout::DiscordId);
+  let mut qb = Query::select();
+  qb.from(UsersSteam::Table);
+  qb.from(UsersDiscord::Table);
+  qb.from(Members::Table);
+  qb.from(MilestoneSettings::Table);
+  qb.and_where(ex_col!(UsersSteam, NekoId).equals(col!(UsersDiscord, NekoId)));
+  qb.and_where(ex_col!(Members, UserId).equals(col!(UsersDiscord, DiscordId)));
+  qb.and_where(ex_col!(MilestoneSettings, GuildId).equals(col!(Members, GuildId)));
+  qb.and_where(ex_col!(UsersSteam, SteamId).is_in(steam_ids));
+  qb.and_where(ex_col!(UsersDiscord, DiscordId).not_in_subquery(optout));
+  qb.column(col!(UsersSteam, SteamId));
+  qb.column(col!(UsersDiscord, DiscordId));
+  qb.column(col!(MilestoneSettings, GuildId));
+  qb.column(col!(MilestoneSettings, ChannelId));
+  qb.column(col!(MilestoneSettings, Hours));
+  qb.column(col!(MilestoneSettings, Games));
+  qb.column(col!(MilestoneSettings, Leaders));
+  Ok(fetch_all!(&qb, Recipient)?)
+}
+
+async fn app_names(apps: Vec<i64>) -> Res<HashMap<i64, String>> {
+  let mut qb = Query::select();
+  qb.from(Apps::Table);
+  qb.columns([Apps::Id, Apps::Name]);
+  qb.and_where(Expr::col(Apps::Id).is_in(apps));
+  Ok(fetch_all!(&qb, (i64, String))?.into_iter().collect())
+}
+
+async fn owned_counts(steam_ids: Vec<i64>) -> Res<HashMap<i64, i64>> {
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  qb.column(Playdata::UserId);
+  qb.expr(Func::count(Expr::col(Playdata::AppId)));
+  qb.and_where(Expr::col(Playdata::UserId).is_in(steam_ids));
+  qb.group_by_col(Playdata::UserId);
+  Ok(fetch_all!(&qb, (i64, i64))?.into_iter().collect())
+}
+
+// Stores the current #1 of the app in the guild, returning the previous one if it changed
+async fn update_leader(guild: i64, app: i64) -> Res<Option<(i64, i64)>> {
+  let mut top = build_top_query(
+    Of::Users,
+    By::Playtime,
+    At::App(app as i32),
+    Some(guild),
+    None,
+  );
+  top.limit(1);
+  let Some(leader) = fetch_optional!(&top, QueryOutput)? else {
+    return Ok(None);
+  };
+  let mut qb = Query::select();
+  qb.from(AppLeaders::Table);
+  qb.column(AppLeaders::DiscordId);
+  qb.and_where(Expr::col(AppLeaders::GuildId).eq(guild));
+  qb.and_where(Expr::col(AppLeaders::AppId).eq(app));
+  let previous = fetch_optional!(&qb, (i64,))?.map(|r| r.0);
+  if previous == Some(leader.id) {
+    return Ok(None);
+  }
+  use AppLeaders::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, AppId, DiscordId]);
+  qb.values([guild.into(), app.into(), leader.id.into()])?;
+  qb.on_conflict(
+    OnConflict::columns([GuildId, AppId])
+      .update_column(DiscordId)
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  // The first leader that is seen isn't news
+  Ok(previous.map(|p| (p, leader.id)))
+}
+
+fn parse_hours(hours: &str) -> Vec<i32> {
+  hours
+    .split(',')
+    .filter_map(|h| h.trim().parse::<i32>().ok())
+    .filter(|h| (1..=MAX_HOURS as i32).contains(h))
+    .collect()
+}
+
+/// Announce milestones crossed between the previous and the current playdata update
+pub async fn announce(c: &Context, changes: Vec<PlaytimeChange>) -> R {
+  if changes.is_empty() {
+    return Ok(());
+  }
+  let users: HashSet<_> = changes.iter().map(|c| c.user).collect();
+  let apps: HashSet<_> = changes.iter().map(|c| c.app).collect();
+  let recipients = recipients(users.iter().copied().collect()).await?;
+  if recipients.is_empty() {
+    return Ok(());
+  }
+  let names = app_names(apps.into_iter().collect()).await?;
+  let owned = owned_counts(users.into_iter().collect()).await?;
+  let name = |app: &i64| names.get(app).cloned().unwrap_or(app.to_string());
+
+  let mut messages: HashMap<i64, Vec<String>> = HashMap::new();
+  let mut leaderboards: HashMap<(i64, i64), HashSet<i64>> = HashMap::new();
+  for r in &recipients {
+    let lines = messages.entry(r.channel_id).or_default();
+    let changes: Vec<_> = changes.iter().filter(|c| c.user == r.steam_id).collect();
+    // Newly linked accounts would otherwise celebrate all of their past playtime at once
+    for change in &changes {
+      let Some(old) = change.old else {
+        continue;
+      };
+      for hours in parse_hours(&r.hours) {
+        if old < hours * 60 && change.new >= hours * 60 {
+          lines.push(format!(
+            "<@{}> just crossed {hours} hours in **{}**!",
+            r.discord_id,
+            name(&change.app)
+          ));
+        }
+      }
+    }
+    let added = changes.iter().filter(|c| c.old.is_none()).count() as i64;
+    let after = *owned.get(&r.steam_id).unwrap_or(&0);
+    let before = after - added;
+    let games = r.games as i64;
+    if games > 0 && before > 0 && before < games && after >= games {
+      lines.push(format!("<@{}> now owns {games} games!", r.discord_id));
+    }
+    if r.leaders {
+      let apps = leaderboards.entry((r.guild_id, r.channel_id)).or_default();
+      apps.extend(changes.iter().map(|c| c.app));
+    }
+  }
+  for ((guild, channel), apps) in leaderboards {
+    for app in apps {
+      if let Some((previous, leader)) = update_leader(guild, app).await? {
+        messages.entry(channel).or_default().push(format!(
+          "<@{leader}> is the new #1 in **{}**, taking over from <@{previous}>!",
+          name(&app)
+        ));
+      }
+    }
+  }
+
+  for (channel, lines) in messages {
+    // Stay under the message length limit
+    let mut content = String::new();
+    for line in lines.into_iter().chain([String::new()]) {
+      if (line.is_empty() || content.len() + line.len() >= 2000) && !content.is_empty() {
+        let res = serenity::ChannelId(channel as u64)
+          .send_message(c, |m| {
+            m.content(&content).allowed_mentions(|a| a.empty_parse())
+          })
+          .await;
+        if let Err(err) = res {
+          log::warn!("Failed to announce milestones in {channel}: {err}");
+        }
+        content.clear();
+      }
+      content += &line;
+      content += "\n";
+    }
+  }
+  Ok(())
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn setup(
+  ctx: Ctx<'_>,
+  channel: GuildChannel,
+  hours: Option<String>,
+  games: Option<u32>,
+  leaders: Option<bool>,
+) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  let hours = hours.unwrap_or(DEFAULT_HOURS.into());
+  let thresholds = parse_hours(&hours);
+  // Every entry has to be valid, instead of silently dropping the ones that aren't
+  if thresholds.is_empty() || thresholds.len() != hours.split(',').count() {
+    ctx
+      .reply(format!(
+        "Hours should be a comma separated list of 1 to {MAX_HOURS}, like 100,500,1000"
+      ))
+      .await?;
+    return Ok(());
+  }
+  let hours = thresholds
+    .iter()
+    .map(|h| h.to_string())
+    .collect::<Vec<_>>()
+    .join(",");
+  let games = games.map_or(DEFAULT_GAMES, |g| g as i32);
+  let mut qb = Query::insert();
+  qb.into_table(MilestoneSettings::Table);
+  qb.columns([
+    MilestoneSettings::GuildId,
+    MilestoneSettings::ChannelId,
+    MilestoneSettings::Hours,
+    MilestoneSettings::Games,
+    MilestoneSettings::Leaders,
+  ]);
+  qb.values([
+    (guild.0 as i64).into(),
+    (channel.id.0 as i64).into(),
+    hours.clone().into(),
+    games.into(),
+    leaders.unwrap_or(true).into(),
+  ])?;
+  qb.on_conflict(
+    OnConflict::column(MilestoneSettings::GuildId)
+      .update_columns([
+        MilestoneSettings::ChannelId,
+        MilestoneSettings::Hours,
+        MilestoneSettings::Games,
+        MilestoneSettings::Leaders,
+      ])
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  ctx
+    .reply(format!(
+      "Milestones will be announced in <#{}>, at {hours} hours played and {games} games owned",
+      channel.id
+    ))
+    .await?;
+  Ok(())
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn disable(ctx: Ctx<'_>) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  let mut qb = Query::delete();
+  qb.from_table(MilestoneSettings::Table);
+  qb.and_where(Expr::col(MilestoneSettings::GuildId).eq(guild.0 as i64));
+  execute!(&qb)?;
+  ctx.reply("Milestones will no longer be announced").await?;
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, ephemeral)]
+pub async fn optout(ctx: Ctx<'_>, enabled: bool) -> R {
+  let user = ctx.author().id.0 as i64;
+  if enabled {
+    let mut qb = Query::insert();
+    qb.into_table(MilestoneOptout::Table);
+    qb.columns([MilestoneOptout::DiscordId]);
+    qb.values([user.into()])?;
+    qb.on_conflict(
+      OnConflict::column(MilestoneOptout::DiscordId)
+        .do_nothing()
+        .to_owned(),
+    );
+    execute!(&qb)?;
+    ctx
+      .reply("Your milestones will no longer be announced")
+      .await?;
+  } else {
+    let mut qb = Query::delete();
+    qb.from_table(MilestoneOptout::Table);
+    qb.and_where(Expr::col(MilestoneOptout::DiscordId).eq(user));
+    execute!(&qb)?;
+    ctx.reply("Your milestones will be announced again").await?;
+  }
+  Ok(())
+}
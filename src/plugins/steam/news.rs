@@ -0,0 +1,264 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::{
+  app_name,
+  interface::{ISteamNews, NewsItem},
+  query::ratelimit,
+  schema::{NewsSeen, NewsSubscriptions},
+};
+use crate::{
+  core::*,
+  modules::{poise::Ctx, reqwest::req},
+  plugins::neko::autocomplete::steam_apps,
+};
+use poise::serenity_prelude::{self as serenity, Context, GuildChannel, Timestamp};
+use regex::Regex;
+use sea_query::{Expr, OnConflict, Query};
+use std::collections::HashSet;
+use unicode_truncate::UnicodeTruncateStr;
+
+// Patch notes are posted as community announcements, other feeds are mostly press coverage
+const FEED: &str = "steam_community_announcements";
+const COUNT: u32 = 10;
+const EXCERPT: usize = 300;
+
+once_cell!(bbcode_blocks, BBCODE_BLOCKS: Regex, {
+  Regex::new(r"(?is)\[(img|previewyoutube|video)[^\]]*\].*?\[/(img|previewyoutube|video)\]").unwrap()
+});
+once_cell!(bbcode_tags, BBCODE_TAGS: Regex, {
+  Regex::new(r"\[/?[a-zA-Z0-9*]+(=[^\]]*)?\]").unwrap()
+});
+
+/// Plain text beginning of a BBCode formatted news post
+async fn excerpt(contents: &str) -> String {
+  let text = bbcode_blocks().await.replace_all(contents, " ");
+  let text = bbcode_tags().await.replace_all(&text, " ");
+  let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  let (short, _) = text.unicode_truncate(EXCERPT);
+  if short.len() < text.len() {
+    format!("{}…", short.trim_end())
+  } else {
+    text
+  }
+}
+
+async fn fetch_news(app: i64) -> Res<Vec<NewsItem>> {
+  ratelimit().await;
+  Ok(
+    req()
+      .get_news_for_app(app as u32, COUNT, FEED)
+      .await?
+      .news
+      .items,
+  )
+}
+
+// Items that weren't posted before
+async fn unseen(app: i64, items: Vec<NewsItem>) -> Res<Vec<NewsItem>> {
+  if items.is_empty() {
+    return Ok(vec![]);
+  }
+  let mut qb = Query::select();
+  qb.from(NewsSeen::Table);
+  qb.column(NewsSeen::Gid);
+  qb.and_where(Expr::col(NewsSeen::AppId).eq(app));
+  qb.and_where(Expr::col(NewsSeen::Gid).is_in(items.iter().map(|i| i.gid.clone())));
+  let seen: HashSet<_> = fetch_all!(&qb, (String,))?
+    .into_iter()
+    .map(|r| r.0)
+    .collect();
+  Ok(items.into_iter().filter(|i| !seen.contains(&i.gid)).collect())
+}
+
+// Remembers the items as seen, so they aren't posted again
+async fn mark_seen(app: i64, items: &[NewsItem]) -> R {
+  if items.is_empty() {
+    return Ok(());
+  }
+  let mut qb = Query::insert();
+  qb.into_table(NewsSeen::Table);
+  qb.columns([NewsSeen::AppId, NewsSeen::Gid, NewsSeen::PublishedAt]);
+  for item in items {
+    qb.values([app.into(), item.gid.clone().into(), item.date.into()])?;
+  }
+  qb.on_conflict(
+    OnConflict::columns([NewsSeen::AppId, NewsSeen::Gid])
+      .do_nothing()
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  Ok(())
+}
+
+async fn post(c: &Context, channel: i64, name: &str, item: &NewsItem) -> R {
+  let excerpt = excerpt(&item.contents).await;
+  serenity::ChannelId(channel as u64)
+    .send_message(c, |m| {
+      m.embed(|e| {
+        e.author(|a| a.name(name))
+          .title(item.title.unicode_truncate(256).0)
+          .url(&item.url)
+          .description(excerpt);
+        if let Ok(date) = Timestamp::from_unix_timestamp(item.date) {
+          e.timestamp(date);
+        }
+        e
+      })
+    })
+    .await?;
+  Ok(())
+}
+
+/// Post news of every subscribed app that weren't posted yet
+pub async fn poll_news(c: &Context) -> R {
+  let mut qb = Query::select();
+  qb.from(NewsSubscriptions::Table);
+  qb.columns([NewsSubscriptions::AppId, NewsSubscriptions::ChannelId]);
+  qb.order_by(NewsSubscriptions::AppId, sea_query::Order::Asc);
+  let subscriptions = fetch_all!(&qb, (i64, i64))?;
+  let mut apps: Vec<_> = subscriptions.iter().map(|s| s.0).collect();
+  apps.dedup();
+  for app in apps {
+    let items = match fetch_news(app).await {
+      Ok(items) => items,
+      Err(err) => {
+        log::warn!("Failed to fetch news of {app}: {err}");
+        continue;
+      }
+    };
+    let mut new = unseen(app, items).await?;
+    if new.is_empty() {
+      continue;
+    }
+    // Newest items come first, post them in the order they were published
+    new.sort_by_key(|i| i.date);
+    let name = app_name(app as i32).await?;
+    let channels: Vec<_> = subscriptions.iter().filter(|s| s.0 == app).map(|s| s.1).collect();
+    let mut posted = vec![];
+    for item in new {
+      let mut sent = false;
+      for &channel in &channels {
+        match post(c, channel, &name, &item).await {
+          Ok(()) => sent = true,
+          Err(err) => log::warn!("Failed to post news of {app} in {channel}: {err}"),
+        }
+      }
+      // Items that couldn't be posted anywhere are retried on the next poll
+      if sent {
+        posted.push(item);
+      }
+    }
+    mark_seen(app, &posted).await?;
+  }
+  Ok(())
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn subscribe(
+  ctx: Ctx<'_>,
+  #[autocomplete = "steam_apps"] app: i32,
+  channel: GuildChannel,
+) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  if channel.guild_id != guild {
+    ctx.reply("That channel isn't in this guild").await?;
+    return Ok(());
+  }
+  let name = app_name(app).await?;
+  ctx.defer().await?;
+  // Only news published from now on get posted, instead of the whole backlog
+  mark_seen(app as i64, &fetch_news(app as i64).await?).await?;
+  let mut qb = Query::insert();
+  qb.into_table(NewsSubscriptions::Table);
+  qb.columns([
+    NewsSubscriptions::ChannelId,
+    NewsSubscriptions::AppId,
+    NewsSubscriptions::GuildId,
+  ]);
+  qb.values([
+    (channel.id.0 as i64).into(),
+    app.into(),
+    (guild.0 as i64).into(),
+  ])?;
+  qb.on_conflict(
+    OnConflict::columns([NewsSubscriptions::ChannelId, NewsSubscriptions::AppId])
+      .do_nothing()
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  ctx
+    .reply(format!(
+      "News of {name} will be posted in <#{}>",
+      channel.id
+    ))
+    .await?;
+  Ok(())
+}
+
+#[poise::command(
+  prefix_command,
+  slash_command,
+  guild_only,
+  required_permissions = "MANAGE_GUILD"
+)]
+pub async fn unsubscribe(
+  ctx: Ctx<'_>,
+  #[autocomplete = "steam_apps"] app: i32,
+  channel: Option<GuildChannel>,
+) -> R {
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  let name = app_name(app).await?;
+  let mut qb = Query::delete();
+  qb.from_table(NewsSubscriptions::Table);
+  qb.and_where(Expr::col(NewsSubscriptions::GuildId).eq(guild.0 as i64));
+  qb.and_where(Expr::col(NewsSubscriptions::AppId).eq(app));
+  if let Some(channel) = &channel {
+    qb.and_where(Expr::col(NewsSubscriptions::ChannelId).eq(channel.id.0 as i64));
+  }
+  let removed = execute!(&qb)?.rows_affected();
+  if removed == 0 {
+    ctx
+      .reply(format!("News of {name} weren't posted here"))
+      .await?;
+  } else {
+    ctx
+      .reply(format!("News of {name} will no longer be posted"))
+      .await?;
+  }
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, guild_only)]
+pub async fn list(ctx: Ctx<'_>) -> R {
+  use super::schema::Apps;
+  let guild = ctx.guild_id().ok_or("Not in a guild")?;
+  let mut qb = Query::select();
+  qb.from(NewsSubscriptions::Table);
+  qb.from(Apps::Table);
+  qb.column(col!(NewsSubscriptions, ChannelId));
+  qb.column(col!(Apps, Name));
+  qb.and_where(ex_col!(NewsSubscriptions, AppId).equals(col!(Apps, Id)));
+  qb.and_where(ex_col!(NewsSubscriptions, GuildId).eq(guild.0 as i64));
+  qb.order_by(col!(Apps, Name), sea_query::Order::Asc);
+  let subscriptions = fetch_all!(&qb, (i64, String))?;
+  let output = if subscriptions.is_empty() {
+    "This guild isn't subscribed to any news".into()
+  } else {
+    subscriptions
+      .into_iter()
+      .map(|(channel, name)| format!("{name} in <#{channel}>"))
+      .collect::<Vec<_>>()
+      .join("\n")
+  };
+  ctx
+    .send(|b| b.embed(|e| e.title("Steam news subscriptions").description(output)))
+    .await?;
+  Ok(())
+}
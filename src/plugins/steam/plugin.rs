@@ -2,57 +2,101 @@
 //
 // This project is dual licensed under MIT and Apache.
 
-use self::query::{build_top_query, update_playdata, update_users, At, By, Of, QueryOutput};
+use self::{
+  interface::IPlayerService,
+  query::{
+    build_common_query, build_history_query, build_top_query, mark_notified, most_played,
+    steam_accounts, steam_totals, unnotified_private, update_achievements, update_all_playdata,
+    update_apps, update_users, user_rank, At, By, Of, ProfileState, QueryOutput,
+  },
+  milestones::announce,
+  news::poll_news,
+  prices::poll_prices,
+  sync::{sync_all, sync_member},
+};
 use crate::{
-  core::*,
+  core::{
+    time::{fmt_sec, Period, Span},
+    *,
+  },
   modules::{
     cron::Cron,
-    poise::{Ctx, EventHandler, Poise},
-    sqlx::Postgres,
+    fluent::tr,
+    poise::{paginate, try_sctx, Ctx, EventHandler, Poise},
+    resvg::{Chart, Resvg, Theme},
+    reqwest::req,
+    sqlx::{count_rows, Postgres},
+  },
+  plugins::{
+    neko::{
+      autocomplete::steam_apps,
+      query::{all_steam_connections, linked_accounts, neko_id},
+    },
   },
-  plugins::neko::query::all_steam_connections,
 };
+use chrono::{Duration, NaiveDate};
 use poise::{
-  serenity_prelude::{
-    ButtonStyle, CollectComponentInteraction, CreateActionRow, InteractionResponseType, Member,
-    ReactionType, Role, RoleId, UserId,
-  },
+  serenity_prelude::{AttachmentType, Context, Member, RoleId, UserId},
   Event,
 };
-use sea_query::Query;
+use sea_query::{Query, SelectStatement};
+use std::collections::HashMap;
 use tokio_cron_scheduler::Job;
 
 pub mod interface;
+pub mod milestones;
+pub mod news;
+pub mod prices;
 pub mod query;
 pub mod schema;
+pub mod sync;
 
 pub struct Steam;
 
 once_cell!(sapi_key, APIKEY: String);
+once_cell!(private_dm, PRIVATEDM: bool);
 
 impl Module for Steam {
   async fn init(&mut self, fw: &mut Framework) -> R {
     APIKEY.set(expect_env!("STEAMAPI_KEY"))?;
+    PRIVATEDM.set(default_env!("STEAM_PRIVATE_DM", "false") == "true")?;
     fw.req_module::<Postgres>().await?;
+    fw.req_module::<Resvg>().await?;
     let poise = fw.req_module::<Poise>().await?;
     poise.commands.push(steam());
-    poise.event_handlers.push(roles());
-    cron!(fw, "0 0 */1 * * *", || { minor_update().await.unwrap() });
-    //cron!(fw, "0 0 0 */7 * *", || { update_apps().await.unwrap() });
+    poise.event_handlers.push(role_handler());
+    cron!(fw, "0 0 */1 * * *", || {
+      minor_update().await.unwrap();
+      match try_sctx() {
+        Some(c) => sync_all(c).await.unwrap(),
+        None => log::warn!("Discord isn't connected yet, skipping Steam role sync"),
+      }
+    });
+    cron!(fw, "0 30 */6 * * *", || { update_achievements().await.unwrap() });
+    cron!(fw, "0 15 */6 * * *", || { update_apps().await.unwrap() });
+    cron!(fw, "0 */30 * * * *", || {
+      if let Some(c) = try_sctx() {
+        poll_news(c).await.unwrap()
+      }
+    });
+    cron!(fw, "0 45 */2 * * *", || {
+      if let Some(c) = try_sctx() {
+        poll_prices(c).await.unwrap()
+      }
+    });
     Ok(())
   }
 }
 
-fn roles() -> EventHandler {
+fn role_handler() -> EventHandler {
   |c, event| {
     Box::pin(async move {
       use Event::*;
       match event {
         GuildMemberAddition { new_member: m } => {
           if !m.user.bot {
-            let roles = filter_roles(&m.roles(c).unwrap_or_default(), get_roles(m).await?);
             let mut member = m.guild_id.member(c, m.user.id).await?;
-            member.add_roles(c, roles.as_slice()).await?;
+            sync_member(c, &mut member).await?;
           }
         }
         _ => {}
@@ -74,6 +118,7 @@ pub async fn get_roles(m: &Member) -> Res<Vec<RoleId>> {
 
   qb.cond_where(ex_col!(steam::DiscordRoles, GuildId).eq(m.guild_id.0 as i64));
   qb.cond_where(ex_col!(steam::DiscordRoles, AppId).equals(col!(steam::Playdata, AppId)));
+  qb.cond_where(ex_col!(steam::Playdata, Playtime).gte(ex_col!(steam::DiscordRoles, MinPlaytime)));
   qb.cond_where(ex_col!(neko::UsersSteam, SteamId).equals(col!(steam::Playdata, UserId)));
   qb.cond_where(ex_col!(neko::UsersSteam, NekoId).equals(col!(neko::UsersDiscord, NekoId)));
   qb.cond_where(ex_col!(neko::UsersDiscord, DiscordId).eq(m.user.id.0 as i64));
@@ -86,266 +131,663 @@ pub async fn get_roles(m: &Member) -> Res<Vec<RoleId>> {
   )
 }
 
-pub fn filter_roles(og: &Vec<Role>, add: Vec<RoleId>) -> Vec<RoleId> {
-  let mapped: Vec<u64> = og.into_iter().map(|r| r.id.0).collect();
-  add.into_iter().filter(|r| !mapped.contains(&r.0)).collect()
-}
-
 pub async fn minor_update() -> R {
-  let c = all_steam_connections().await?;
-  update_users(&c).await?;
-  update_playdata(&c).await?;
+  update_users(&all_steam_connections().await?).await?;
+  let changes = update_all_playdata().await?;
+  match try_sctx() {
+    Some(c) => {
+      if let Err(err) = announce(c, changes).await {
+        log::warn!("Failed to announce Steam milestones: {err}");
+      }
+    }
+    None => log::warn!("Discord isn't connected yet, skipping Steam milestones"),
+  }
+  // Owners stay unnotified until Discord is connected
+  if let (true, Some(c)) = (*private_dm(), try_sctx()) {
+    notify_private(c).await?;
+  }
   Ok(())
 }
 
-cmd_group!(steam, "user", "app", "top");
+/// Tell owners of private profiles how to make their game details public, once per account
+async fn notify_private(c: &Context) -> R {
+  let private = unnotified_private().await?;
+  if private.is_empty() {
+    return Ok(());
+  }
+  for (steam_id, discord_id) in &private {
+    let msg = format!(
+      "The game details of your Steam account <https://steamcommunity.com/profiles/{steam_id}> \
+      are private, so its playtime can't be tracked. Set \"Game details\" to public at \
+      <https://steamcommunity.com/my/edit/settings>, it will be picked up within an hour."
+    );
+    let user = UserId(*discord_id as u64);
+    let sent = async {
+      let dm = user.create_dm_channel(c).await?;
+      dm.send_message(c, |m| m.content(msg)).await
+    };
+    if let Err(err) = sent.await {
+      log::warn!("Failed to notify {user} about private Steam account {steam_id}: {err}");
+    }
+  }
+  mark_notified(private.into_iter().map(|p| p.0).collect()).await
+}
+
+cmd_group!(
+  steam,
+  "user",
+  "app",
+  "guild",
+  "roles",
+  "milestones",
+  "news",
+  "top",
+  "profile",
+  "common",
+  "whoplays",
+  "prices::watch",
+  "prices::unwatch",
+  "prices::watchlist"
+);
+
+cmd_group!(user, "user::top", "user::history");
+cmd_group!(app, "app::top", "app::history");
+cmd_group!(guild, "guild::top");
+cmd_group!(roles, "roles::add", "roles::remove", "roles::list");
+cmd_group!(news, "news::subscribe", "news::unsubscribe", "news::list");
+cmd_group!(
+  milestones,
+  "milestones::setup",
+  "milestones::disable",
+  "milestones::optout"
+);
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn top(
+  ctx: Ctx<'_>,
+  by: By,
+  of: Of,
+  global: Option<bool>,
+  period: Option<Period>,
+  since: Option<String>,
+  until: Option<String>,
+) -> R {
+  let (span, during) = parse_span(period, since, until)?;
+  // A guild top of guilds would only ever have one entry
+  let guild = match (global.unwrap_or(false), &of) {
+    (true, _) | (_, Of::Guilds) => None,
+    _ => ctx.guild_id().map(|g| g.0 as i64),
+  };
+  let title = match guild {
+    Some(_) => format!("Top of {of} by {by}{during}"),
+    None => format!("Global top of {of} by {by}{during}"),
+  };
+  handle(ctx, title, of, by, At::None, guild, span).await
+}
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn profile(ctx: Ctx<'_>, user: Option<UserId>) -> R {
+  ctx.defer().await?;
+  let user = user.unwrap_or(ctx.author().id);
+  let discord_id = user.0 as i64;
+  let accounts = steam_accounts(discord_id).await?;
+  let Some(main) = accounts.first() else {
+    ctx
+      .reply("This user has no linked Steam accounts, link one at <https://link.neko.rs>")
+      .await?;
+    return Ok(());
+  };
 
-cmd_group!(user, "user::top");
-cmd_group!(app, "app::top");
+  // Linked accounts always have a neko user
+  let neko = neko_id(discord_id).await?.ok_or("Unknown neko user")?;
+  let (playtime, owned) = steam_totals(neko).await?;
+  let mut games = String::new();
+  for (name, playtime) in most_played(neko, 5).await? {
+    games += &format!("`{: >6}` {}\n", fmt_sec(playtime * 60), name);
+  }
+  let guild = ctx.guild_id().map(|g| g.0 as i64);
+  let scope = match guild {
+    Some(_) => "Guild rank",
+    None => "Global rank",
+  };
+  let rank = match user_rank(discord_id, guild).await? {
+    Some(rank) => format!("#{rank}"),
+    None => "-".into(),
+  };
+  let level = match req().get_steam_level(sapi_key(), main.id as u64).await {
+    Ok(res) => res
+      .response
+      .player_level
+      .map_or("-".into(), |l| l.to_string()),
+    Err(_) => "-".into(),
+  };
+
+  let steam = accounts
+    .iter()
+    .map(|a| {
+      let name = a.name.clone().unwrap_or(a.id.to_string());
+      let name = match &a.profile_url {
+        Some(url) => format!("[{name}]({url})"),
+        None => name,
+      };
+      match a.state.as_deref().and_then(ProfileState::parse) {
+        Some(ProfileState::Ok) | None => name,
+        Some(state) => format!("{name} ({})", state.describe()),
+      }
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  let mut linked = String::new();
+  for (provider, id) in linked_accounts(neko).await? {
+    if provider != "discord" && provider != "steam" {
+      linked += &format!("{provider}: `{id}`\n");
+    }
+  }
+
+  let name = user.to_user(ctx).await?.name;
+  ctx
+    .send(|b| {
+      b.embed(|e| {
+        e.title(format!("{name}'s Steam profile"));
+        if let Some(avatar) = &main.avatar {
+          e.thumbnail(avatar);
+        }
+        e.field("Level", level, true)
+          .field("Playtime", fmt_sec(playtime * 60), true)
+          .field("Games", owned, true)
+          .field(scope, rank, true);
+        if let Some(last) = main.last_online {
+          e.field("Last online", format!("<t:{last}:R>"), true);
+        }
+        if !games.is_empty() {
+          e.field("Top games", games, false);
+        }
+        e.field("Steam accounts", steam, false);
+        if !linked.is_empty() {
+          e.field("Linked accounts", linked, false);
+        }
+        e
+      })
+    })
+    .await?;
+  Ok(())
+}
 
 #[poise::command(prefix_command, slash_command)]
-pub async fn top(ctx: Ctx<'_>, by: By, of: Of) -> R {
-  handle(ctx, format!("Top of {of} by {by}"), of, by, At::None).await
+pub async fn common(
+  ctx: Ctx<'_>,
+  first: UserId,
+  second: Option<UserId>,
+  third: Option<UserId>,
+  fourth: Option<UserId>,
+) -> R {
+  let mut users: Vec<_> = [Some(first), second, third, fourth]
+    .into_iter()
+    .flatten()
+    .collect();
+  // With a single user given, compare them against yourself
+  if users.len() == 1 {
+    users.push(ctx.author().id);
+  }
+  users.sort();
+  users.dedup();
+  let mentions: Vec<_> = users.iter().map(|u| format!("<@{u}>")).collect();
+  let title = format!("Games owned by {}, by combined playtime", mentions.join(", "));
+  let qb = build_common_query(users.into_iter().map(|u| u.0 as i64).collect());
+  handle_query(ctx, title, Of::Apps, By::Playtime, qb).await
+}
+
+#[poise::command(prefix_command, slash_command, guild_only)]
+pub async fn whoplays(ctx: Ctx<'_>, #[autocomplete = "steam_apps"] app: i32) -> R {
+  let name = app_name(app).await?;
+  let title = format!("Members that own {name}, by playtime");
+  let guild = ctx.guild_id().map(|g| g.0 as i64);
+  let qb = build_top_query(Of::Users, By::Playtime, At::App(app), guild, None);
+  handle_query(ctx, title, Of::Users, By::Playtime, qb).await
+}
+
+/// Resolve the optional period parameters of a top, custom ranges take priority over periods
+fn parse_span(
+  period: Option<Period>,
+  since: Option<String>,
+  until: Option<String>,
+) -> Res<(Option<Span>, String)> {
+  Ok(match (since, period) {
+    (Some(since), _) => {
+      let span = Span::parse(&since, until.as_deref())?;
+      let until = until.unwrap_or("today".into());
+      (Some(span), format!(" from {since} to {until}"))
+    }
+    (None, Some(period)) => (
+      Some(period.span()),
+      format!(" over the last {}", period.to_string().to_lowercase()),
+    ),
+    (None, None) => (None, String::new()),
+  })
 }
 //context_menu_command = "gwaa"
 
 mod user {
   use super::{
-    handle,
+    handle, handle_history, parse_span,
     query::{At, By, Of},
   };
-  use crate::{core::R, modules::poise::Ctx};
+  use crate::{
+    core::{time::Period, R},
+    modules::{poise::Ctx, resvg::Theme},
+    plugins::neko::autocomplete::steam_apps,
+  };
   use poise::serenity_prelude::UserId;
 
   #[poise::command(prefix_command, slash_command)]
-  pub async fn top(ctx: Ctx<'_>, by: By, user: Option<UserId>) -> R {
+  pub async fn top(
+    ctx: Ctx<'_>,
+    by: By,
+    user: Option<UserId>,
+    period: Option<Period>,
+    since: Option<String>,
+    until: Option<String>,
+  ) -> R {
+    let (span, during) = parse_span(period, since, until)?;
     let user = user.unwrap_or(ctx.author().id);
-    let title = format!("Users's ({user}) top of apps by {by}");
-    handle(ctx, title, Of::Apps, by, At::User(user.0 as i64)).await
+    let title = format!("Users's ({user}) top of apps by {by}{during}");
+    handle(ctx, title, Of::Apps, by, At::User(user.0 as i64), None, span).await
   }
-}
-mod app {
-  use sea_query::Query;
-use sqlx::Column;
 
-use crate::{
-    core::R,
+  #[poise::command(prefix_command, slash_command)]
+  pub async fn history(
+    ctx: Ctx<'_>,
+    #[autocomplete = "steam_apps"] app: Option<i32>,
+    user: Option<UserId>,
+    period: Option<Period>,
+    theme: Option<Theme>,
+  ) -> R {
+    let user = user.unwrap_or(ctx.author().id);
+    let (title, at) = match app {
+      Some(app) => {
+        let name = super::app_name(app).await?;
+        let title = format!("{}'s {name} playtime", user.to_user(ctx).await?.name);
+        (title, At::UserApp(user.0 as i64, app))
+      }
+      None => {
+        let title = format!("{}'s playtime", user.to_user(ctx).await?.name);
+        (title, At::User(user.0 as i64))
+      }
+    };
+    handle_history(ctx, title, at, None, period, theme).await
+  }
+}
+mod guild {
+  use crate::{
+    core::{time::Period, R},
     modules::poise::Ctx,
     plugins::{
-      neko::autocomplete::steam_apps,
+      discord::schema::Guilds,
+      neko::autocomplete::discord_guilds,
       steam::{
-        handle,
+        handle, parse_span,
         query::{At, By, Of},
       },
     },
   };
+  use sea_query::Query;
 
   #[poise::command(prefix_command, slash_command)]
-  pub async fn top(ctx: Ctx<'_>, by: By, #[autocomplete = "steam_apps"] app: i32) -> R {
-    use super::schema::Apps::{self, *};
+  pub async fn top(
+    ctx: Ctx<'_>,
+    by: By,
+    of: Of,
+    #[autocomplete = "discord_guilds"] guild: Option<String>,
+    period: Option<Period>,
+    since: Option<String>,
+    until: Option<String>,
+  ) -> R {
+    // Scoped to one guild, a top of guilds would only ever have one entry
+    if matches!(of, Of::Guilds) {
+      ctx
+        .reply("Guilds can only be ranked globally, use `/steam top` instead")
+        .await?;
+      return Ok(());
+    }
+    let (span, during) = parse_span(period, since, until)?;
+    let guild = match guild {
+      Some(guild) => guild.parse::<i64>()?,
+      None => match ctx.guild_id() {
+        Some(guild) => guild.0 as i64,
+        None => {
+          ctx.reply("Specify a guild when using this command outside of one").await?;
+          return Ok(());
+        }
+      },
+    };
+    // Only whitelisted guilds are scraped, so this doubles as the whitelist check
     let mut qb = Query::select();
-    qb.from(Table);
-    qb.columns([Name]);
-    qb.and_where(ex_col!(Apps, Id).eq(app));
-    let (name,) = fetch_one!(&qb, (String,)).unwrap();
-
-    let title = format!("Top {name} gamers by {by}");
-    handle(ctx, title, Of::Users, by, At::App(app)).await
+    qb.from(Guilds::Table);
+    qb.column(Guilds::Name);
+    qb.and_where(ex_col!(Guilds, Id).eq(guild));
+    let Some((name,)) = fetch_optional!(&qb, (String,))? else {
+      ctx.reply("This guild is not available").await?;
+      return Ok(());
+    };
+
+    let title = format!("Top of {of} in {name} by {by}{during}");
+    handle(ctx, title, of, by, At::None, Some(guild), span).await
   }
 }
+mod roles {
+  use crate::{
+    core::R,
+    modules::poise::Ctx,
+    plugins::{
+      neko::autocomplete::steam_apps,
+      steam::{
+        app_name,
+        query::{add_role_mapping, remove_role_mapping, role_candidates, role_mappings},
+        MAX_HOURS,
+        sync::revoke_role,
+      },
+    },
+  };
+  use poise::serenity_prelude::{Permissions, Role, RoleId, UserId};
+
+  #[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_ROLES"
+  )]
+  pub async fn add(
+    ctx: Ctx<'_>,
+    #[autocomplete = "steam_apps"] app: i32,
+    role: Role,
+    min_hours: Option<u32>,
+  ) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = app_name(app).await?;
+    let min_hours = min_hours.unwrap_or(0);
+    if min_hours > MAX_HOURS {
+      ctx
+        .reply(format!("The minimum playtime can be at most {MAX_HOURS} hours"))
+        .await?;
+      return Ok(());
+    }
+    if role.id.0 == guild.0 || role.managed {
+      ctx.reply("This role can't be granted to members").await?;
+      return Ok(());
+    }
+    // Bots can only manage roles below their own highest role
+    let bot = guild.member(ctx, ctx.framework().bot_id).await?;
+    let position = bot.highest_role_info(ctx).map(|(_, p)| p).unwrap_or(0);
+    if !bot.permissions(ctx)?.contains(Permissions::MANAGE_ROLES) || role.position >= position {
+      ctx
+        .reply(format!(
+          "I'm not able to manage {}, make sure my role is above it",
+          role.name
+        ))
+        .await?;
+      return Ok(());
+    }
+    let mappings = role_mappings(guild.0 as i64).await?;
+    if let Some((_, other, other_name, _)) = mappings
+      .iter()
+      .find(|(r, a, _, _)| *r == role.id.0 as i64 && *a != app as i64)
+    {
+      ctx
+        .reply(format!(
+          "{} is already mapped to {other_name} ({other})",
+          role.name
+        ))
+        .await?;
+      return Ok(());
+    }
+    let min_playtime = min_hours as i32 * 60;
+    add_role_mapping(guild.0 as i64, role.id.0 as i64, app as i64, min_playtime).await?;
+    // The role the app was mapped to before is no longer granted by anything
+    let previous = mappings
+      .iter()
+      .find(|(r, a, _, _)| *a == app as i64 && *r != role.id.0 as i64)
+      .map(|(r, _, _, _)| RoleId(*r as u64));
+    if let Some(previous) = previous {
+      revoke_role(ctx.serenity_context(), guild, previous).await?;
+    }
 
-const SIZE: u64 = 15;
-const PAGES: u64 = 100; //todo
-
-fn divdec(f: i64, s: i64) -> (i64, i64) {
-  (f / s, f * 10 / s % 10)
-}
-
-fn fmt_sec(num: i64) -> String {
-  let (mf, md) = divdec(num, 60);
-  let (hf, hd) = divdec(num, 3600);
-  let (df, dd) = divdec(num, 86400);
-  let (wf, wd) = divdec(num, 604800);
-  let (yf, yd) = divdec(num, 31556926);
-
-  if mf < 1 {
-    format!("{num}s")
-  } else if mf < 10 {
-    format!("{mf}.{md}m")
-  } else if hf < 1 {
-    format!("{mf}m")
-  } else if hf < 10 {
-    format!("{hf}.{hd}h")
-  } else if df < 1 {
-    format!("{hf}h")
-  } else if df < 10 {
-    format!("{df}.{dd}d")
-  } else if wf < 1 {
-    format!("{df}d")
-  } else if wf < 10 {
-    format!("{wf}.{wd}w")
-  } else if yf < 1 {
-    format!("{wf}w")
-  } else if yf < 10 {
-    format!("{yf}.{yd}y")
-  } else {
-    format!("{yf}y")
-  }
-}
-
-
-async fn handle(ctx: Ctx<'_>, input: String, of: Of, by: By, at: At) -> R {
-  let mut msg = ctx
-    .send(|b| {
-      b.content(input.clone()).components(|b| {
-        b.create_action_row(|b| pagination_buttons(b, 0, 0, true, "pg_disp".into()))
-      })
+    let m = ctx
+      .reply(format!(
+        "Mapped {name} to {}, granting it to current members...",
+        role.name
+      ))
+      .await?;
+    let mut granted = 0;
+    for user in role_candidates(guild.0 as i64, app as i64, min_playtime).await? {
+      let Ok(mut member) = guild.member(ctx, UserId(user as u64)).await else {
+        continue;
+      };
+      if !member.roles.contains(&role.id) {
+        match member.add_role(ctx, role.id).await {
+          Ok(()) => granted += 1,
+          Err(err) => log::warn!("Failed to grant {} to {user}: {err}", role.id),
+        }
+      }
+    }
+    m.edit(ctx, |m| {
+      m.content(format!(
+        "Mapped {name} to {}, granted it to {granted} members",
+        role.name
+      ))
     })
-    .await?
-    .into_message()
     .await?;
+    Ok(())
+  }
 
-  let bys = match by {
-    By::Playtime => "hours",
-    By::Ownership => "copies",
-  };
-
-  let isuser = Of::Users == of;
-  let divider = By::Playtime == by;
-  let qb = build_top_query(of, by, at, ctx.guild_id().map(|a| a.0 as i64).unwrap_or(1232659990993702943));
+  #[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_ROLES"
+  )]
+  pub async fn remove(ctx: Ctx<'_>, #[autocomplete = "steam_apps"] app: i32) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = app_name(app).await?;
+    match remove_role_mapping(guild.0 as i64, app as i64).await? {
+      Some(role) => {
+        let m = ctx
+          .reply(format!("Removed the mapping of {name} to <@&{role}>, revoking it..."))
+          .await?;
+        let revoked = revoke_role(ctx.serenity_context(), guild, RoleId(role as u64)).await?;
+        m.edit(ctx, |m| {
+          m.content(format!(
+            "Removed the mapping of {name} to <@&{role}>, revoked it from {revoked} members"
+          ))
+        })
+        .await?
+      }
+      None => {
+        ctx
+          .reply(format!("{name} is not mapped to any role"))
+          .await?;
+      }
+    };
+    Ok(())
+  }
 
-  let get_page = async move |page: u64| -> Res<String> {
-    let mut pb = qb.clone();
-    pb.limit(SIZE);
-    pb.offset(page * SIZE);
-    //if page == 0 {
-    //  pb.limit(SIZE + 1);
-    //} else {
-    //  pb.limit(SIZE + 2);
-    //  pb.offset(page * SIZE - 1);
-    //}
-    let data = fetch_all!(&pb, QueryOutput)?;
+  #[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_ROLES"
+  )]
+  pub async fn list(ctx: Ctx<'_>) -> R {
+    let guild = ctx.guild_id().ok_or("Not in a guild")?;
+    let mappings = role_mappings(guild.0 as i64).await?;
+    if mappings.is_empty() {
+      ctx
+        .reply("There are no Steam app roles in this guild")
+        .await?;
+      return Ok(());
+    }
     let mut output = String::new();
-    let data: Vec<_> = data.into_iter().map(|a| (if divider { fmt_sec(a.sum_count * 60) } else { format!("{}", a.sum_count)}, a.id, a.name) ).collect();
-    let max = data.iter().map(|(a,b,c)|a.len()).max().unwrap_or(5);
-    for (c, id, name) in data {
-      if (isuser) {
-
-        output += &format!("`{c: >max$}` <@{id}>\n");
-      } else {
-        output += &format!("`{c: >max$} | {name}` \n");
+    for (role, app, name, min_playtime) in mappings {
+      output += &format!("<@&{role}> | {name} ({app})");
+      if min_playtime > 0 {
+        output += &format!(" | {}h+", min_playtime / 60);
       }
+      output += "\n";
     }
-    Ok(output)
+    ctx
+      .send(|b| b.embed(|e| e.title("Steam app roles").description(output)))
+      .await?;
+    Ok(())
+  }
+}
+mod app {
+  use crate::{
+    core::{time::Period, R},
+    modules::{poise::Ctx, resvg::Theme},
+    plugins::{
+      neko::autocomplete::steam_apps,
+      steam::{
+        app_name, handle, handle_history, parse_span,
+        query::{At, By, Of},
+      },
+    },
   };
 
-  let mut page = 0;
-  let firstpage = get_page(page).await?;
+  #[poise::command(prefix_command, slash_command)]
+  pub async fn top(
+    ctx: Ctx<'_>,
+    by: By,
+    #[autocomplete = "steam_apps"] app: i32,
+    period: Option<Period>,
+    since: Option<String>,
+    until: Option<String>,
+  ) -> R {
+    let (span, during) = parse_span(period, since, until)?;
+    let name = app_name(app).await?;
+
+    let title = format!("Top {name} gamers by {by}{during}");
+    let guild = ctx.guild_id().map(|g| g.0 as i64);
+    handle(ctx, title, Of::Users, by, At::App(app), guild, span).await
+  }
 
-  msg
-    .edit(ctx, |b| {
-      b.content(format!("{input}\n{firstpage}\nTo add your steam to this list, head over to <https://link.neko.rs>"))
-        .components(|b| {
-          b.create_action_row(|b| pagination_buttons(b, page, PAGES, false, "".into()))
-        })
-    })
-    .await?;
+  #[poise::command(prefix_command, slash_command)]
+  pub async fn history(
+    ctx: Ctx<'_>,
+    #[autocomplete = "steam_apps"] app: i32,
+    period: Option<Period>,
+    theme: Option<Theme>,
+  ) -> R {
+    let name = app_name(app).await?;
+    // Aggregated over the current guild, or over every guild in DMs
+    let guild = ctx.guild_id().map(|g| g.0 as i64);
+    let title = format!("{name} playtime");
+    handle_history(ctx, title, At::App(app), guild, period, theme).await
+  }
+}
 
-  let mut id = msg.id.0;
-
-  while let Some(press) = CollectComponentInteraction::new(ctx)
-    .message_id(msg.id)
-    .timeout(std::time::Duration::from_secs(300))
-    .await
-  {
-    match press.data.custom_id.as_str() {
-      "pg_prev" => page -= 1,
-      "pg_next" => page += 1,
-      _ => {}
-    }
+// Upper bound of hour thresholds, so they can't overflow once converted to minutes
+pub const MAX_HOURS: u32 = 100_000;
 
-    press
-      .create_interaction_response(ctx, |f| {
-        f.kind(InteractionResponseType::UpdateMessage)
-          .interaction_response_data(|b| {
-            b.components(|b| {
-              b.create_action_row(|b| {
-                pagination_buttons(b, page, PAGES, true, press.data.custom_id.clone())
-              })
-            })
-          })
-      })
-      .await?;
-
-    let pageee = get_page(page).await.unwrap();
-
-    let mut msg = press.get_interaction_response(ctx).await?;
-    msg
-      .edit(ctx, |b| {
-        b.content(format!("{input}\n{pageee}\nTo add your steam to this list, head over to <https://link.neko.rs>"))
-          .components(|b| {
-            b.create_action_row(|b| {
-              pagination_buttons(b, page, PAGES, false, press.data.custom_id.clone())
-            })
-          })
-      })
-      .await?;
+pub async fn app_name(app: i32) -> Res<String> {
+  use schema::Apps::{self, *};
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.columns([Name]);
+  qb.and_where(ex_col!(Apps, Id).eq(app));
+  Ok(fetch_optional!(&qb, (String,))?.ok_or("Unknown app")?.0)
+}
 
-    id = msg.id.0;
-  }
+async fn handle_history(
+  ctx: Ctx<'_>,
+  title: String,
+  at: At,
+  guild: Option<i64>,
+  period: Option<Period>,
+  theme: Option<Theme>,
+) -> R {
+  ctx.defer().await?;
+  let span = period.unwrap_or(Period::Month).span();
+  let data: HashMap<i32, i64> =
+    fetch_all!(&build_history_query(at, guild, span), (i32, i64))?.into_iter().collect();
+  let locale = ctx.locale();
+  let chart = Chart {
+    theme: theme.unwrap_or(Theme::Dark),
+    title: title.clone(),
+    x_label: tr(locale, "steam_history", Some("x"), None),
+    y_label: tr(locale, "steam_history", Some("y"), None),
+    points: (span.from..=span.to)
+      .map(|day| (fmt_day(day), *data.get(&day).unwrap_or(&0) as f64 / 60.0))
+      .collect(),
+  };
+  let png = chart.render()?;
   ctx
-    .http()
-    .get_message(msg.channel_id.0, id)
-    .await?
-    .edit(ctx, |m| {
-      m.components(|b| b.create_action_row(|b| pagination_buttons(b, page, PAGES, true, "".into())))
+    .send(|b| {
+      b.content(title).attachment(AttachmentType::Bytes {
+        data: png.into(),
+        filename: "history.png".into(),
+      })
     })
     .await?;
   Ok(())
 }
 
-pub fn pagination_buttons(
-  b: &mut CreateActionRow,
-  page: u64,
-  pages: u64,
-  loading: bool,
-  event: String,
-) -> &mut CreateActionRow {
-  let l = ReactionType::Custom {
-    animated: true,
-    id: poise::serenity_prelude::EmojiId(1233072462527332363),
-    name: None,
-  };
-  b.create_button(|b| {
-    if event == "pg_prev" && loading {
-      b.emoji(l.clone())
-    } else {
-      b.label("<")
-    }
-    .custom_id(format!("pg_prev"))
-    .style(ButtonStyle::Secondary)
-    .disabled(loading || page == 0)
-  });
-  b.create_button(|b| {
-    if event == "pg_disp" && loading {
-      b.emoji(l.clone())
-    } else {
-      b.label(format!("{}/{pages}", page + 1))
-    }
-    .custom_id(format!("pg_disp"))
-    .style(ButtonStyle::Secondary)
-    .disabled(true)
-  });
-  b.create_button(|b| {
-    if event == "pg_next" && loading {
-      b.emoji(l)
-    } else {
-      b.label(">")
-    }
-    .custom_id(format!("pg_next"))
-    .style(ButtonStyle::Secondary)
-    .disabled(loading || page + 1 == pages)
-  });
+const SIZE: u64 = 15;
+
+// Format an utc day as month and day of month
+fn fmt_day(day: i32) -> String {
+  let date = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default() + Duration::days(day as i64);
+  date.format("%m-%d").to_string()
+}
+
+fn fmt_by(by: &By, num: i64) -> String {
+  match by {
+    By::Playtime | By::Average => fmt_sec(num * 60),
+    By::Completion => format!("{num}%"),
+    By::Ownership | By::Achievements | By::Perfect => format!("{num}"),
+  }
+}
 
-  b
+
+async fn handle(
+  ctx: Ctx<'_>,
+  input: String,
+  of: Of,
+  by: By,
+  at: At,
+  guild: Option<i64>,
+  span: Option<Span>,
+) -> R {
+  let qb = build_top_query(of, by, at, guild, span);
+  handle_query(ctx, input, of, by, qb).await
+}
+
+// Paginates any query with the same output columns as the top query
+async fn handle_query(ctx: Ctx<'_>, input: String, of: Of, by: By, qb: SelectStatement) -> R {
+  let isuser = Of::Users == of;
+  let total = count_rows(qb.clone());
+  paginate(ctx, total, SIZE, |page| {
+    let mut pb = qb.clone();
+    pb.limit(SIZE);
+    pb.offset(page * SIZE);
+    let input = input.clone();
+    async move {
+      let data = fetch_all!(&pb, QueryOutput)?;
+      let data: Vec<_> = data
+        .into_iter()
+        .map(|a| (fmt_by(&by, a.sum_count), a.id, a.name))
+        .collect();
+      let max = data.iter().map(|(c, _, _)| c.len()).max().unwrap_or(5);
+      let mut output = String::new();
+      for (c, id, name) in data {
+        if isuser {
+          output += &format!("`{c: >max$}` <@{id}>\n");
+        } else {
+          output += &format!("`{c: >max$} | {name}` \n");
+        }
+      }
+      Ok(format!(
+        "{input}\n{output}\nTo add your steam to this list, head over to <https://link.neko.rs>"
+      ))
+    }
+  })
+  .await
 }
@@ -0,0 +1,375 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::{
+  app_name,
+  interface::{PriceData, PriceOverview, StoreApi},
+  query::STORE_BUDGET,
+  schema::{Apps, PriceHistory, PriceWatches},
+};
+use crate::{
+  core::*,
+  modules::{poise::Ctx, reqwest::req},
+  plugins::neko::autocomplete::steam_apps,
+};
+use chrono::Utc;
+use itertools::Itertools;
+use poise::serenity_prelude::{self as serenity, Context, GuildChannel, Permissions, UserId};
+use sea_query::{Expr, OnConflict, Order, Query};
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+// Apps per price overview request
+const CHUNK: usize = 100;
+
+once_cell!(price_regions, PRICE_REGIONS: Vec<String>, {
+  default_env!("STEAM_PRICE_REGIONS", "us")
+    .split(',')
+    .map(|r| r.trim().to_lowercase())
+    .filter(|r| !r.is_empty())
+    .collect()
+});
+
+#[derive(FromRow)]
+struct Watch {
+  discord_id: i64,
+  app_id: i64,
+  region: String,
+  target: Option<i32>,
+  channel_id: Option<i64>,
+  alerted: bool,
+}
+
+// Latest known price of each app in the region, as (price, discount)
+async fn last_prices(region: &str) -> Res<HashMap<i64, (i32, i32)>> {
+  use PriceHistory::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.columns([AppId, Price, Discount]);
+  qb.distinct_on([AppId]);
+  qb.and_where(Expr::col(Region).eq(region));
+  qb.order_by(AppId, Order::Asc);
+  qb.order_by(CheckedAt, Order::Desc);
+  Ok(
+    fetch_all!(&qb, (i64, i32, i32))?
+      .into_iter()
+      .map(|(app, price, discount)| (app, (price, discount)))
+      .collect(),
+  )
+}
+
+// Fetches current prices of the apps, recording the ones that changed
+async fn update_prices(region: &str, apps: Vec<i64>) -> Res<HashMap<i64, PriceOverview>> {
+  let last = last_prices(region).await?;
+  let mut prices = HashMap::new();
+  for chunk in apps.chunks(CHUNK) {
+    let ids = chunk.iter().map(|a| a.to_string()).join(",");
+    STORE_BUDGET.wait().await;
+    let res = match req()
+      .get_price_overviews(ids, region, "price_overview")
+      .await
+    {
+      Ok(res) => res,
+      Err(err) => {
+        log::warn!("Failed to get prices in region '{region}': {err}");
+        continue;
+      }
+    };
+    for (app, result) in res {
+      let Ok(app) = app.parse::<i64>() else {
+        continue;
+      };
+      if !result.success {
+        continue;
+      }
+      if let Some(PriceData::Priced { price_overview }) = result.data {
+        prices.insert(app, price_overview);
+      }
+    }
+  }
+
+  let now = Utc::now().timestamp();
+  let mut qb = Query::insert();
+  qb.into_table(PriceHistory::Table);
+  qb.columns([
+    PriceHistory::AppId,
+    PriceHistory::Region,
+    PriceHistory::Currency,
+    PriceHistory::Initial,
+    PriceHistory::Price,
+    PriceHistory::Discount,
+    PriceHistory::CheckedAt,
+  ]);
+  let mut changed = 0;
+  for (app, p) in &prices {
+    if last.get(app) == Some(&(p.price, p.discount_percent)) {
+      continue;
+    }
+    qb.values([
+      (*app).into(),
+      region.into(),
+      p.currency.clone().into(),
+      p.initial.into(),
+      p.price.into(),
+      p.discount_percent.into(),
+      now.into(),
+    ])?;
+    changed += 1;
+  }
+  if changed > 0 {
+    qb.on_conflict(
+      OnConflict::columns([
+        PriceHistory::AppId,
+        PriceHistory::Region,
+        PriceHistory::CheckedAt,
+      ])
+      .do_nothing()
+      .to_owned(),
+    );
+    execute!(&qb)?;
+  }
+  log::info!(
+    "Updated prices of {} apps in region '{region}', {changed} changed",
+    prices.len()
+  );
+  Ok(prices)
+}
+
+async fn alert(c: &Context, watch: &Watch, price: &PriceOverview) -> R {
+  let name = app_name(watch.app_id as i32).await?;
+  let content = match watch.target {
+    Some(target) => format!(
+      "**{name}** dropped to {} (target {:.2})",
+      price.final_formatted,
+      target as f64 / 100.0
+    ),
+    None => format!(
+      "**{name}** is {}% off, now {} instead of {}",
+      price.discount_percent, price.final_formatted, price.initial_formatted
+    ),
+  };
+  let content = format!("{content}\nhttps://store.steampowered.com/app/{}", watch.app_id);
+  let channel = match watch.channel_id {
+    Some(channel) => serenity::ChannelId(channel as u64),
+    None => {
+      UserId(watch.discord_id as u64)
+        .create_dm_channel(c)
+        .await?
+        .id
+    }
+  };
+  channel
+    .send_message(c, |m| {
+      if watch.channel_id.is_some() {
+        m.content(format!("<@{}> {content}", watch.discord_id))
+      } else {
+        m.content(content)
+      }
+    })
+    .await?;
+  Ok(())
+}
+
+async fn set_alerted(watch: &Watch, alerted: bool) -> R {
+  let mut qb = Query::update();
+  qb.table(PriceWatches::Table);
+  qb.value(PriceWatches::Alerted, alerted);
+  qb.and_where(Expr::col(PriceWatches::DiscordId).eq(watch.discord_id));
+  qb.and_where(Expr::col(PriceWatches::AppId).eq(watch.app_id));
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Check prices of every watched app, alerting watchers of apps that went on sale or below target
+pub async fn poll_prices(c: &Context) -> R {
+  let mut qb = Query::select();
+  qb.from(PriceWatches::Table);
+  qb.columns([
+    PriceWatches::DiscordId,
+    PriceWatches::AppId,
+    PriceWatches::Region,
+    PriceWatches::Target,
+    PriceWatches::ChannelId,
+    PriceWatches::Alerted,
+  ]);
+  let watches = fetch_all!(&qb, Watch)?;
+  let mut regions: HashMap<&str, Vec<i64>> = HashMap::new();
+  for watch in &watches {
+    regions.entry(&watch.region).or_default().push(watch.app_id);
+  }
+  let mut prices = HashMap::new();
+  for (region, mut apps) in regions {
+    apps.sort();
+    apps.dedup();
+    prices.insert(region, update_prices(region, apps).await?);
+  }
+
+  for watch in &watches {
+    let Some(price) = prices
+      .get(watch.region.as_str())
+      .and_then(|p| p.get(&watch.app_id))
+    else {
+      continue;
+    };
+    let triggered = match watch.target {
+      Some(target) => price.price <= target,
+      None => price.discount_percent > 0,
+    };
+    if triggered && !watch.alerted {
+      if let Err(err) = alert(c, watch, price).await {
+        log::warn!("Failed to alert {} of a sale: {err}", watch.discord_id);
+      }
+      set_alerted(watch, true).await?;
+    } else if !triggered && watch.alerted {
+      set_alerted(watch, false).await?;
+    }
+  }
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, guild_only, ephemeral)]
+pub async fn watch(
+  ctx: Ctx<'_>,
+  #[autocomplete = "steam_apps"] app: i32,
+  target: Option<f64>,
+  region: Option<String>,
+  channel: Option<GuildChannel>,
+) -> R {
+  let regions = price_regions().await;
+  let region = match region.map(|r| r.trim().to_lowercase()) {
+    Some(region) if regions.contains(&region) => region,
+    Some(_) => {
+      ctx
+        .reply(format!(
+          "Prices are only checked in: {}",
+          regions.join(", ")
+        ))
+        .await?;
+      return Ok(());
+    }
+    None => regions
+      .first()
+      .ok_or("No price regions configured")?
+      .clone(),
+  };
+  if target.is_some_and(|t| t < 0.0) {
+    ctx.reply("The target price can't be negative").await?;
+    return Ok(());
+  }
+  // Alerts can only be sent to channels of this guild, where you could post them yourself
+  if let Some(channel) = &channel {
+    if ctx.guild_id() != Some(channel.guild_id) {
+      ctx
+        .reply("Alerts can only be posted in channels of this guild")
+        .await?;
+      return Ok(());
+    }
+    let perms = channel.permissions_for_user(ctx, ctx.author().id)?;
+    if !perms.contains(Permissions::SEND_MESSAGES) {
+      ctx
+        .reply(format!("You can't send messages in <#{}>", channel.id))
+        .await?;
+      return Ok(());
+    }
+  }
+  let name = app_name(app).await?;
+  let mut qb = Query::insert();
+  qb.into_table(PriceWatches::Table);
+  qb.columns([
+    PriceWatches::DiscordId,
+    PriceWatches::AppId,
+    PriceWatches::Region,
+    PriceWatches::Target,
+    PriceWatches::ChannelId,
+    PriceWatches::Alerted,
+  ]);
+  qb.values([
+    (ctx.author().id.0 as i64).into(),
+    app.into(),
+    region.clone().into(),
+    target.map(|t| (t * 100.0).round() as i32).into(),
+    channel.as_ref().map(|c| c.id.0 as i64).into(),
+    false.into(),
+  ])?;
+  qb.on_conflict(
+    OnConflict::columns([PriceWatches::DiscordId, PriceWatches::AppId])
+      .update_columns([
+        PriceWatches::Region,
+        PriceWatches::Target,
+        PriceWatches::ChannelId,
+        PriceWatches::Alerted,
+      ])
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  let when = match target {
+    Some(target) => format!("drops to {target:.2} or less"),
+    None => "goes on sale".into(),
+  };
+  let dest = match channel {
+    Some(channel) => format!("in <#{}>", channel.id),
+    None => "in DMs".into(),
+  };
+  ctx
+    .reply(format!(
+      "You'll be alerted {dest} when {name} {when} in region '{region}'"
+    ))
+    .await?;
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, ephemeral)]
+pub async fn unwatch(ctx: Ctx<'_>, #[autocomplete = "steam_apps"] app: i32) -> R {
+  let name = app_name(app).await?;
+  let mut qb = Query::delete();
+  qb.from_table(PriceWatches::Table);
+  qb.and_where(Expr::col(PriceWatches::DiscordId).eq(ctx.author().id.0 as i64));
+  qb.and_where(Expr::col(PriceWatches::AppId).eq(app));
+  if execute!(&qb)?.rows_affected() == 0 {
+    ctx.reply(format!("You aren't watching {name}")).await?;
+  } else {
+    ctx
+      .reply(format!("You'll no longer be alerted of {name}"))
+      .await?;
+  }
+  Ok(())
+}
+
+#[poise::command(prefix_command, slash_command, ephemeral)]
+pub async fn watchlist(ctx: Ctx<'_>) -> R {
+  let mut qb = Query::select();
+  qb.from(PriceWatches::Table);
+  qb.from(Apps::Table);
+  qb.column(col!(Apps, Name));
+  qb.column(col!(PriceWatches, Region));
+  qb.column(col!(PriceWatches, Target));
+  qb.column(col!(PriceWatches, ChannelId));
+  qb.and_where(ex_col!(PriceWatches, AppId).equals(col!(Apps, Id)));
+  qb.and_where(ex_col!(PriceWatches, DiscordId).eq(ctx.author().id.0 as i64));
+  qb.order_by(col!(Apps, Name), Order::Asc);
+  let watches = fetch_all!(&qb, (String, String, Option<i32>, Option<i64>))?;
+  let output = if watches.is_empty() {
+    "You aren't watching any apps".into()
+  } else {
+    watches
+      .into_iter()
+      .map(|(name, region, target, channel)| {
+        let target = match target {
+          Some(target) => format!("below {:.2}", target as f64 / 100.0),
+          None => "on sale".into(),
+        };
+        let channel = match channel {
+          Some(channel) => format!("<#{channel}>"),
+          None => "DMs".into(),
+        };
+        format!("{name} {target} in '{region}', alerted in {channel}")
+      })
+      .collect::<Vec<_>>()
+      .join("\n")
+  };
+  ctx
+    .send(|b| b.embed(|e| e.title("Watched Steam apps").description(output)))
+    .await?;
+  Ok(())
+}
@@ -3,66 +3,204 @@
 // This project is dual licensed under MIT and Apache.
 
 use super::{
-  interface::{IPlayerService, ISteamApps, ISteamUser},
+  interface::{IPlayerService, ISteamUser, ISteamUserStats, IStoreService, OwnedApp, StoreApi},
   sapi_key,
 };
-use crate::{core::*, modules::reqwest::req, plugins::*};
-use chrono::Utc;
+use crate::{
+  core::{time::Span, *},
+  modules::reqwest::{req, RateLimit},
+  plugins::*,
+};
+use chrono::{NaiveDate, Utc};
+use futures::StreamExt;
 use poise::ChoiceParameter;
-use sea_query::{Alias, Expr, Func, OnConflict, Order, Query, SelectStatement, WindowStatement};
+use sea_query::{
+  Alias, Cond, Expr, Func, NullOrdering, OnConflict, Order, Query, SelectStatement, SimpleExpr,
+  WindowStatement,
+};
 use sqlx::FromRow;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Mutex};
+
+// Store pages per run whose details are fetched, as the store api has a far lower budget
+const DETAILS_PER_RUN: u64 = 200;
+
+pub static STORE_BUDGET: RateLimit = RateLimit::new(std::time::Duration::from_millis(1500));
 
+/// Sync apps modified since the last sync, separately for each type as the list doesn't include it
 pub async fn update_apps() -> R {
   use steam::schema::Apps::*;
-  log::info!("Updating Steam apps");
-  let apps = req().get_app_list().await?.applist.apps;
-  for apps_chunk in apps.chunks(10000) {
+  // Start of the last complete sync, apps modified during it are simply synced again
+  let since = {
+    use steam::schema::UpdateRuns;
+    let mut qb = Query::select();
+    qb.from(UpdateRuns::Table);
+    qb.column(UpdateRuns::StartedAt);
+    qb.and_where(Expr::col(UpdateRuns::Kind).eq("apps"));
+    qb.and_where(Expr::col(UpdateRuns::FinishedAt).is_not_null());
+    fetch_optional!(&qb, (i64,))?.map_or(0, |r| r.0)
+  };
+  let started = Utc::now().timestamp();
+  log::info!("Updating Steam apps modified since {since}");
+  for kind in ["game", "dlc", "software", "video", "hardware"] {
+    let mut last_appid = 0;
+    loop {
+      ratelimit().await;
+      let list = req()
+        .get_store_apps(
+          sapi_key(),
+          since,
+          kind == "game",
+          kind == "dlc",
+          kind == "software",
+          kind == "video",
+          kind == "hardware",
+          last_appid,
+          10000,
+        )
+        .await?
+        .response;
+      if !list.apps.is_empty() {
+        let mut qb = Query::insert();
+        qb.into_table(Table);
+        qb.columns([Id, Name, AppType, LastModified]);
+        qb.on_conflict(
+          OnConflict::column(Id)
+            .update_columns([Name, AppType, LastModified])
+            .to_owned(),
+        );
+        for app in &list.apps {
+          qb.values([
+            (app.id as i64).into(),
+            app.name.clone().into(),
+            kind.into(),
+            app.last_modified.into(),
+          ])?;
+        }
+        execute!(&qb)?;
+        log::info!("Updated {} apps of type {kind}", list.apps.len());
+      }
+      match (list.have_more_results, list.last_appid) {
+        (true, Some(id)) => last_appid = id,
+        _ => break,
+      }
+    }
+  }
+  // Only moved forward once every type was synced, so apps of an interrupted sync aren't skipped
+  {
+    use steam::schema::UpdateRuns::*;
     let mut qb = Query::insert();
     qb.into_table(Table);
-    qb.columns([Id, Name]);
-    qb.on_conflict(OnConflict::column(Id).update_column(Name).to_owned());
-    for app in apps_chunk {
-      qb.values([(app.id as i64).into(), app.name.clone().into()])?;
-    }
+    qb.columns([Kind, StartedAt, FinishedAt]);
+    qb.values(["apps".into(), started.into(), Utc::now().timestamp().into()])?;
+    qb.on_conflict(
+      OnConflict::column(Kind)
+        .update_columns([StartedAt, FinishedAt])
+        .to_owned(),
+    );
     execute!(&qb)?;
-    log::info!("Updated {} apps", apps_chunk.len());
   }
+  update_app_details().await?;
   log::info!("Finished updating Steam apps");
   Ok(())
 }
 
+// Release dates are only on store pages, owned games and recently modified ones go first
+async fn update_app_details() -> R {
+  use steam::schema::{Apps::*, Playdata};
+  let apps = {
+    let mut sq = Query::select();
+    sq.from(Playdata::Table);
+    sq.column(Playdata::AppId);
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.column(Id);
+    qb.and_where(Expr::col(AppType).eq("game"));
+    qb.cond_where(
+      Cond::any()
+        .add(Expr::col(DetailsAt).is_null())
+        .add(Expr::col(DetailsAt).lt(Expr::col(LastModified))),
+    );
+    qb.order_by_expr(Expr::col(Id).in_subquery(sq), Order::Desc);
+    qb.order_by(LastModified, Order::Desc);
+    qb.limit(DETAILS_PER_RUN);
+    fetch_all!(&qb, (i64,))?
+  };
+  for (app,) in apps {
+    STORE_BUDGET.wait().await;
+    let details = match req().get_app_details(app as u32, "release_date").await {
+      Ok(mut res) => res.remove(&app.to_string()),
+      Err(err) => {
+        log::warn!("Failed to get store details of app '{app}': {err}");
+        continue;
+      }
+    };
+    let day = details
+      .filter(|d| d.success)
+      .and_then(|d| d.data?.release_date)
+      .filter(|r| !r.coming_soon)
+      .and_then(|r| parse_release_date(&r.date));
+    let mut qb = Query::update();
+    qb.table(Table);
+    qb.value(ReleaseDay, day);
+    qb.value(DetailsAt, Utc::now().timestamp());
+    qb.and_where(Expr::col(Id).eq(app));
+    execute!(&qb)?;
+  }
+  Ok(())
+}
+
+// Release date as an utc day, the store uses both day first and month first formats
+fn parse_release_date(date: &str) -> Option<i32> {
+  let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)?;
+  ["%d %b, %Y", "%b %d, %Y", "%d %B, %Y", "%B %d, %Y"]
+    .iter()
+    .find_map(|f| NaiveDate::parse_from_str(date, f).ok())
+    .map(|d| (d - epoch).num_days() as i32)
+}
+
 pub async fn update_users(user_list: &Vec<(i64,)>) -> R {
   log::info!("Updating Steam users");
   let mut profiles = vec![];
   for chunk in user_list.chunks(100) {
-    match futures::join!(
-      req().get_player_summaries(
+    ratelimit().await;
+    match req()
+      .get_player_summaries(
         sapi_key(),
         chunk
           .into_iter()
           .map(|i| i.0.to_string())
           .collect::<Vec<String>>()
           .join(","),
-      ),
-      ratelimit()
-    ) {
-      (Ok(res), _) => {
+      )
+      .await
+    {
+      Ok(res) => {
         for user in res.response.players {
-          profiles.push((user.id.parse::<i64>()?, user.name));
+          profiles.push((user.id.parse::<i64>()?, user));
         }
       }
-      (Err(err), _) => log::warn!("Failed to get '{}' profile summaries: {err}", chunk.len()),
+      Err(err) => log::warn!("Failed to get '{}' profile summaries: {err}", chunk.len()),
     }
   }
   for chunk in profiles.chunks(10000) {
     use steam::schema::Users::*;
     let mut qb = Query::insert();
     qb.into_table(Table);
-    qb.columns([Id, Name]);
-    qb.on_conflict(OnConflict::column(Id).update_column(Name).to_owned());
-    for v in chunk {
-      qb.values([v.0.into(), v.1.clone().into()])?;
+    qb.columns([Id, Name, Avatar, LastOnline, ProfileUrl, Visibility]);
+    qb.on_conflict(
+      OnConflict::column(Id)
+        .update_columns([Name, Avatar, LastOnline, ProfileUrl, Visibility])
+        .to_owned(),
+    );
+    for (id, user) in chunk {
+      qb.values([
+        (*id).into(),
+        user.name.clone().into(),
+        user.avatar.clone().into(),
+        user.last_logoff.into(),
+        user.profile_url.clone().into(),
+        user.visibility.into(),
+      ])?;
     }
     execute!(&qb)?;
   }
@@ -70,110 +208,596 @@ pub async fn update_users(user_list: &Vec<(i64,)>) -> R {
   Ok(())
 }
 
+// Shared by every Steam API request, regardless of how many run concurrently
+static BUDGET: RateLimit = RateLimit::new(std::time::Duration::from_millis(1600));
+
 pub async fn ratelimit() {
-  std::thread::sleep(std::time::Duration::from_millis(1600));
+  BUDGET.wait().await
 }
 
-pub async fn update_playdata(user_list: &Vec<(i64,)>) -> R {
-  // Yes a day, is never exactly the same, but I just need to round the timestamp to current day
-  let day = (Utc::now().timestamp() / 86400) as i32;
-  log::info!("Updating Steam playdata");
-  let mut games = HashMap::new();
-  let mut playdata = vec![];
-  for user in user_list {
-    if let (Ok(res), _) = futures::join!(
-      req().get_owned_games(sapi_key(), user.0 as u64, true, true, false),
-      ratelimit()
-    ) {
-      for game in res.response.games {
-        games.insert(game.id as i64, game.name);
-        playdata.push((user.0, game.id as i64, game.playtime as i32));
+// Amount of users that are fetched concurrently, while one waits on the budget others write to the db
+const WORKERS: usize = 4;
+// Owned games are fetched daily, in between recently played games are enough to track playtime
+const FULL_INTERVAL: i64 = 86400;
+
+#[derive(FromRow)]
+struct PendingUser {
+  id: i64,
+  full: bool,
+  // Profiles that were never returned by the summaries endpoint don't exist
+  known: bool,
+}
+
+/// Users that weren't checked since `since`, most recently online first
+async fn pending_users(user_list: Option<&Vec<(i64,)>>, since: i64) -> Res<Vec<PendingUser>> {
+  use neko::schema::UsersSteam;
+  use steam::schema::{FetchState, Users};
+  let now = Utc::now().timestamp();
+  let mut qb = Query::select();
+  qb.from(UsersSteam::Table);
+  qb.left_join(
+    Users::Table,
+    ex_col!(Users, Id).equals(col!(UsersSteam, SteamId)),
+  );
+  qb.left_join(
+    FetchState::Table,
+    ex_col!(FetchState, SteamId).equals(col!(UsersSteam, SteamId)),
+  );
+  qb.expr_as(ex_col!(UsersSteam, SteamId), Alias::new("id"));
+  qb.expr_as(
+    ex_col!(FetchState, FullAt)
+      .is_null()
+      .or(ex_col!(FetchState, FullAt).lt(now - FULL_INTERVAL)),
+    Alias::new("full"),
+  );
+  qb.expr_as(ex_col!(Users, Id).is_not_null(), Alias::new("known"));
+  if let Some(user_list) = user_list {
+    qb.and_where(ex_col!(UsersSteam, SteamId).is_in(user_list.iter().map(|u| u.0)));
+  }
+  qb.cond_where(
+    Cond::any()
+      .add(ex_col!(FetchState, CheckedAt).is_null())
+      .add(ex_col!(FetchState, CheckedAt).lt(since)),
+  );
+  qb.order_by_with_nulls(col!(Users, LastOnline), Order::Desc, NullOrdering::Last);
+  Ok(fetch_all!(&qb, PendingUser)?)
+}
+
+// Kinds of updates running in this process, so a cron firing during a long run skips instead of overlapping
+static RUNNING: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+/// Held while an update of this kind runs, released on drop
+struct RunLock(&'static str);
+
+impl RunLock {
+  fn acquire(kind: &'static str) -> Option<Self> {
+    let mut running = RUNNING.lock().unwrap();
+    if running.contains(&kind) {
+      return None;
+    }
+    running.push(kind);
+    Some(Self(kind))
+  }
+}
+
+impl Drop for RunLock {
+  fn drop(&mut self) {
+    RUNNING.lock().unwrap().retain(|k| *k != self.0);
+  }
+}
+
+/// Start of the unfinished run of this kind if the last one crashed, otherwise starts a new run
+async fn begin_run(kind: &str) -> Res<i64> {
+  use steam::schema::UpdateRuns::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(StartedAt);
+  qb.and_where(Expr::col(Kind).eq(kind));
+  qb.and_where(Expr::col(FinishedAt).is_null());
+  if let Some((started,)) = fetch_optional!(&qb, (i64,))? {
+    log::info!("Resuming Steam {kind} update started at {started}");
+    return Ok(started);
+  }
+  let now = Utc::now().timestamp();
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([Kind, StartedAt, FinishedAt]);
+  qb.values([kind.into(), now.into(), Option::<i64>::None.into()])?;
+  qb.on_conflict(
+    OnConflict::column(Kind)
+      .update_columns([StartedAt, FinishedAt])
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  Ok(now)
+}
+
+async fn finish_run(kind: &str) -> R {
+  use steam::schema::UpdateRuns::*;
+  let mut qb = Query::update();
+  qb.table(Table);
+  qb.value(FinishedAt, Utc::now().timestamp());
+  qb.and_where(Expr::col(Kind).eq(kind));
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Update playdata of every linked account, resuming the previous run if it didn't finish
+pub async fn update_all_playdata() -> Res<Vec<PlaytimeChange>> {
+  let Some(_lock) = RunLock::acquire("playdata") else {
+    log::warn!("Steam playdata is still being updated, skipping");
+    return Ok(vec![]);
+  };
+  let started = begin_run("playdata").await?;
+  let changes = fetch_playdata(pending_users(None, started).await?).await;
+  finish_run("playdata").await?;
+  Ok(changes)
+}
+
+/// Update playdata of the given accounts right away, used when an account gets linked
+pub async fn update_playdata(user_list: &Vec<(i64,)>) -> Res<Vec<PlaytimeChange>> {
+  let now = Utc::now().timestamp() + 1;
+  Ok(fetch_playdata(pending_users(Some(user_list), now).await?).await)
+}
+
+/// Playtime of a playdata row before and after an update, `old` is unset for newly owned apps
+pub struct PlaytimeChange {
+  pub user: i64,
+  pub app: i64,
+  pub old: Option<i32>,
+  pub new: i32,
+}
+
+async fn fetch_playdata(users: Vec<PendingUser>) -> Vec<PlaytimeChange> {
+  log::info!("Updating Steam playdata of {} users", users.len());
+  let mut results = futures::stream::iter(users)
+    .map(|user| async move { (user.id, update_user(&user).await) })
+    .buffer_unordered(WORKERS);
+  let mut changes = vec![];
+  while let Some((id, res)) = results.next().await {
+    match res {
+      Ok(mut rows) => changes.append(&mut rows),
+      Err(err) => log::warn!("Failed to update playdata of user '{id}': {err}"),
+    }
+  }
+  log::info!(
+    "Finished updating Steam playdata, {} rows changed",
+    changes.len()
+  );
+  changes
+}
+
+// Fetches and stores the playdata of a single user, returning the changed rows
+async fn update_user(user: &PendingUser) -> Res<Vec<PlaytimeChange>> {
+  ratelimit().await;
+  let res = if user.full {
+    req()
+      .get_owned_games(sapi_key(), user.id as u64, true, true, false)
+      .await
+  } else {
+    req()
+      .get_recently_played_games(sapi_key(), user.id as u64, 0)
+      .await
+  };
+  let games = match res {
+    Ok(res) => match (res.response.count, res.response.games) {
+      (_, Some(games)) => games,
+      (Some(_), None) => vec![],
+      (None, None) if user.known => {
+        record_state(user.id, ProfileState::Private, None, false).await?;
+        return Ok(vec![]);
       }
-    } else {
-      log::warn!("Failed to get playdata of user '{}'", user.0);
+      (None, None) => {
+        record_state(user.id, ProfileState::NotFound, None, false).await?;
+        return Ok(vec![]);
+      }
+    },
+    Err(err) => {
+      record_state(user.id, ProfileState::Error, Some(err.to_string()), false).await?;
+      return Err(err);
     }
+  };
+  let changed = store_playdata(user.id, games).await?;
+  record_state(user.id, ProfileState::Ok, None, user.full).await?;
+  Ok(changed)
+}
+
+// Only rows whose playtime changed are written, along with today's history of those rows
+async fn store_playdata(user: i64, mut games: Vec<OwnedApp>) -> Res<Vec<PlaytimeChange>> {
+  if games.is_empty() {
+    return Ok(vec![]);
   }
-  for chunk in games.into_iter().collect::<Vec<_>>().chunks(10000) {
+  // Apps are shared between users fetched concurrently, upserting them in the same order
+  // keeps the row locks from deadlocking
+  games.sort_by_key(|g| g.id);
+  games.dedup_by_key(|g| g.id);
+  // Yes a day, is never exactly the same, but I just need to round the timestamp to current day
+  let day = (Utc::now().timestamp() / 86400) as i32;
+  let excluded = Alias::new("excluded");
+  {
     use steam::schema::Apps::*;
     let mut qb = Query::insert();
     qb.into_table(Table);
     qb.columns([Id, Name]);
-    qb.on_conflict(OnConflict::column(Id).update_column(Name).to_owned());
-    for v in chunk {
-      qb.values([v.0.into(), v.1.clone().into()])?;
+    qb.on_conflict(
+      OnConflict::column(Id)
+        .update_column(Name)
+        .action_and_where(Expr::col((Table, Name)).ne(Expr::col((excluded.clone(), Name))))
+        .to_owned(),
+    );
+    for game in &games {
+      qb.values([(game.id as i64).into(), game.name.clone().into()])?;
     }
     execute!(&qb)?;
-    log::info!("Updated {} apps", chunk.len());
   }
-  for chunk in playdata.chunks(10000) {
-    let updates = {
-      use steam::schema::Playdata::*;
-      let mut qb = Query::insert();
-      qb.into_table(Table);
-      qb.columns([UserId, AppId, Playtime]);
-      qb.on_conflict(
-        OnConflict::columns([UserId, AppId])
-          .update_column(Playtime)
-          .to_owned(),
-      );
-      qb.returning(Query::returning().columns([Id, Playtime]));
-      for v in chunk {
-        qb.values([v.0.into(), v.1.into(), v.2.into()])?;
-      }
-      fetch_all!(&qb, (i64, i32))?
-    };
-    log::trace!("Updated {} playdata rows", chunk.len());
-    {
-      use steam::schema::PlaydataHistory::*;
+  let old: HashMap<i64, i32> = {
+    use steam::schema::Playdata::*;
+    let mut qb = Query::select();
+    qb.from(Table);
+    qb.columns([AppId, Playtime]);
+    qb.and_where(Expr::col(UserId).eq(user));
+    fetch_all!(&qb, (i64, i32))?.into_iter().collect()
+  };
+  let updates = {
+    use steam::schema::Playdata::*;
+    let mut qb = Query::insert();
+    qb.into_table(Table);
+    qb.columns([UserId, AppId, Playtime]);
+    qb.on_conflict(
+      OnConflict::columns([UserId, AppId])
+        .update_column(Playtime)
+        .action_and_where(Expr::col((Table, Playtime)).ne(Expr::col((excluded, Playtime))))
+        .to_owned(),
+    );
+    qb.returning(Query::returning().columns([Id, AppId, Playtime]));
+    for game in &games {
+      qb.values([
+        user.into(),
+        (game.id as i64).into(),
+        (game.playtime as i32).into(),
+      ])?;
+    }
+    fetch_all!(&qb, (i64, i64, i32))?
+  };
+  if updates.is_empty() {
+    return Ok(vec![]);
+  }
+  {
+    use steam::schema::PlaydataHistory::*;
+    let mut qb = Query::insert();
+    qb.into_table(Table);
+    qb.columns([PlaydataId, UtcDay, Playtime]);
+    qb.on_conflict(
+      OnConflict::columns([PlaydataId, UtcDay])
+        .update_column(Playtime)
+        .to_owned(),
+    );
+    for v in &updates {
+      qb.values([v.0.into(), day.into(), v.2.into()])?;
+    }
+    execute!(&qb)?;
+    // Apps owned since the last fetch start from nothing the day before, so their first day counts,
+    // unlike the playtime accounts already had when they were first fetched
+    let new: Vec<_> = updates
+      .iter()
+      .filter(|v| !old.is_empty() && !old.contains_key(&v.1) && v.2 > 0)
+      .collect();
+    if !new.is_empty() {
       let mut qb = Query::insert();
       qb.into_table(Table);
       qb.columns([PlaydataId, UtcDay, Playtime]);
       qb.on_conflict(
         OnConflict::columns([PlaydataId, UtcDay])
-          .update_column(Playtime)
+          .do_nothing()
           .to_owned(),
       );
-      for v in updates {
-        qb.values([v.0.into(), day.into(), v.1.into()])?;
+      for v in new {
+        qb.values([v.0.into(), (day - 1).into(), 0.into()])?;
       }
       execute!(&qb)?;
     }
-    log::trace!("Updated {} playdata history rows", chunk.len());
   }
-  log::info!("Finished updating Steam playdata");
+  Ok(
+    updates
+      .into_iter()
+      .map(|(_, app, new)| PlaytimeChange {
+        user,
+        app,
+        old: old.get(&app).copied(),
+        new,
+      })
+      .collect(),
+  )
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ProfileState {
+  Ok,
+  Private,
+  NotFound,
+  Error,
+}
+
+impl ProfileState {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ProfileState::Ok => "ok",
+      ProfileState::Private => "private",
+      ProfileState::NotFound => "not_found",
+      ProfileState::Error => "error",
+    }
+  }
+
+  pub fn parse(state: &str) -> Option<Self> {
+    match state {
+      "ok" => Some(ProfileState::Ok),
+      "private" => Some(ProfileState::Private),
+      "not_found" => Some(ProfileState::NotFound),
+      "error" => Some(ProfileState::Error),
+      _ => None,
+    }
+  }
+
+  pub fn describe(&self) -> &'static str {
+    match self {
+      ProfileState::Ok => "ok",
+      ProfileState::Private => "game details are private",
+      ProfileState::NotFound => "profile not found",
+      ProfileState::Error => "failed to fetch",
+    }
+  }
+}
+
+async fn record_state(id: i64, state: ProfileState, error: Option<String>, full: bool) -> R {
+  use steam::schema::FetchState::*;
+  let now = Utc::now().timestamp();
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([SteamId, State, Error, CheckedAt, FullAt]);
+  qb.values([
+    id.into(),
+    state.as_str().into(),
+    error.into(),
+    now.into(),
+    full.then_some(now).into(),
+  ])?;
+  // The time of the last full fetch is kept, unless this was one
+  let mut update = vec![State, Error, CheckedAt];
+  if full {
+    update.push(FullAt);
+  }
+  qb.on_conflict(
+    OnConflict::column(SteamId)
+      .update_columns(update)
+      .to_owned(),
+  );
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Fetch state of every Steam account linked to the neko user
+pub async fn fetch_states(neko_id: i32) -> Res<Vec<(i64, Option<String>, Option<i64>)>> {
+  use neko::schema::UsersSteam;
+  use steam::schema::FetchState;
+  let mut qb = Query::select();
+  qb.from(UsersSteam::Table);
+  qb.left_join(
+    FetchState::Table,
+    ex_col!(FetchState, SteamId).equals(col!(UsersSteam, SteamId)),
+  );
+  qb.column(col!(UsersSteam, SteamId));
+  qb.column(col!(FetchState, State));
+  qb.column(col!(FetchState, CheckedAt));
+  qb.and_where(ex_col!(UsersSteam, NekoId).eq(neko_id));
+  Ok(fetch_all!(&qb, (i64, Option<String>, Option<i64>))?)
+}
+
+/// Private accounts whose owners were not yet notified, paired with the linked discord users
+pub async fn unnotified_private() -> Res<Vec<(i64, i64)>> {
+  use neko::schema::{UsersDiscord, UsersSteam};
+  use steam::schema::FetchState;
+  let mut qb = Query::select();
+  qb.from(FetchState::Table);
+  qb.from(UsersSteam::Table);
+  qb.from(UsersDiscord::Table);
+  qb.and_where(ex_col!(UsersSteam, SteamId).equals(col!(FetchState, SteamId)));
+  qb.and_where(ex_col!(UsersDiscord, NekoId).equals(col!(UsersSteam, NekoId)));
+  qb.and_where(ex_col!(FetchState, State).eq(ProfileState::Private.as_str()));
+  qb.and_where(ex_col!(FetchState, Notified).eq(false));
+  qb.column(col!(FetchState, SteamId));
+  qb.column(col!(UsersDiscord, DiscordId));
+  Ok(fetch_all!(&qb, (i64, i64))?)
+}
+
+pub async fn mark_notified(steam_ids: Vec<i64>) -> R {
+  use steam::schema::FetchState::*;
+  let mut qb = Query::update();
+  qb.table(Table);
+  qb.value(Notified, true);
+  qb.and_where(Expr::col(SteamId).is_in(steam_ids));
+  execute!(&qb)?;
+  Ok(())
+}
+
+// Achievement schemas of played apps are refetched weekly
+const SCHEMA_INTERVAL: i64 = 7 * 86400;
+
+pub async fn update_achievements() -> R {
+  let Some(_lock) = RunLock::acquire("achievements") else {
+    log::warn!("Steam achievements are still being updated, skipping");
+    return Ok(());
+  };
+  log::info!("Updating Steam achievements");
+  // Schemas of apps that were never checked before, or not checked in a while
+  let apps = {
+    use steam::schema::{AppAchievements, Playdata};
+    let mut qb = Query::select();
+    qb.from(Playdata::Table);
+    qb.left_join(
+      AppAchievements::Table,
+      ex_col!(AppAchievements, AppId).equals(col!(Playdata, AppId)),
+    );
+    qb.column(col!(Playdata, AppId));
+    qb.distinct();
+    qb.and_where(ex_col!(Playdata, Playtime).gt(0));
+    qb.cond_where(
+      Cond::any()
+        .add(ex_col!(AppAchievements, AppId).is_null())
+        .add(ex_col!(AppAchievements, CheckedAt).lt(Utc::now().timestamp() - SCHEMA_INTERVAL)),
+    );
+    fetch_all!(&qb, (i64,))?
+  };
+  for (app,) in apps {
+    ratelimit().await;
+    let total = match req().get_schema_for_game(sapi_key(), app as u32).await {
+      Ok(res) => res.game.stats.map(|s| s.achievements.len()).unwrap_or(0),
+      Err(err) => {
+        log::warn!("Failed to get achievement schema of app '{app}': {err}");
+        continue;
+      }
+    };
+    use steam::schema::AppAchievements::*;
+    let mut qb = Query::insert();
+    qb.into_table(Table);
+    qb.columns([AppId, Total, CheckedAt]);
+    qb.on_conflict(
+      OnConflict::column(AppId)
+        .update_columns([Total, CheckedAt])
+        .to_owned(),
+    );
+    qb.values([app.into(), (total as i32).into(), Utc::now().timestamp().into()])?;
+    execute!(&qb)?;
+  }
+  // Only apps with achievements, that were played since their last update
+  let pending = {
+    use steam::schema::{AppAchievements, Playdata, PlaydataAchievements};
+    let mut qb = Query::select();
+    qb.from(Playdata::Table);
+    qb.columns([
+      col!(Playdata, Id),
+      col!(Playdata, UserId),
+      col!(Playdata, AppId),
+      col!(Playdata, Playtime),
+    ]);
+    qb.inner_join(
+      AppAchievements::Table,
+      ex_col!(AppAchievements, AppId).equals(col!(Playdata, AppId)),
+    );
+    qb.left_join(
+      PlaydataAchievements::Table,
+      ex_col!(PlaydataAchievements, PlaydataId).equals(col!(Playdata, Id)),
+    );
+    qb.and_where(ex_col!(AppAchievements, Total).gt(0));
+    qb.and_where(ex_col!(Playdata, Playtime).gt(0));
+    qb.cond_where(
+      Cond::any()
+        .add(ex_col!(PlaydataAchievements, PlaydataId).is_null())
+        .add(ex_col!(PlaydataAchievements, Playtime).not_equals(col!(Playdata, Playtime))),
+    );
+    fetch_all!(&qb, (i64, i64, i64, i32))?
+  };
+  log::info!("Updating {} Steam achievement rows", pending.len());
+  for (id, user, app, playtime) in pending {
+    ratelimit().await;
+    match req()
+      .get_player_achievements(sapi_key(), user as u64, app as u32)
+      .await
+    {
+      Ok(res) if res.playerstats.success => {
+        let achieved = res
+          .playerstats
+          .achievements
+          .iter()
+          .filter(|a| a.achieved != 0)
+          .count();
+        use steam::schema::PlaydataAchievements::*;
+        let mut qb = Query::insert();
+        qb.into_table(Table);
+        qb.columns([PlaydataId, Achieved, Playtime]);
+        qb.on_conflict(
+          OnConflict::column(PlaydataId)
+            .update_columns([Achieved, Playtime])
+            .to_owned(),
+        );
+        qb.values([id.into(), (achieved as i32).into(), playtime.into()])?;
+        execute!(&qb)?;
+      }
+      // Private game details, or apps that don't expose stats
+      Ok(_) => log::trace!("No achievements of user '{user}' in app '{app}'"),
+      Err(err) => {
+        log::warn!("Failed to get achievements of user '{user}' in app '{app}': {err}")
+      }
+    }
+  }
+  log::info!("Finished updating Steam achievements");
   Ok(())
 }
 
-#[derive(ChoiceParameter, PartialEq)]
+#[derive(ChoiceParameter, PartialEq, Clone, Copy)]
 pub enum By {
   Playtime,
-  Ownership, // TODO: TopCompleteon (achievemetns)
+  Average,
+  Ownership,
+  Achievements, // Unlocked achievements
+  Completion,   // Average completion percentage
+  Perfect,      // Apps with every achievement unlocked
+}
+
+impl By {
+  pub fn is_achievement(&self) -> bool {
+    matches!(self, By::Achievements | By::Completion | By::Perfect)
+  }
 }
 
+#[derive(Clone, Copy)]
 pub enum At {
   User(i64),
   App(i32),
+  UserApp(i64, i32),
   None,
 }
 
-#[derive(ChoiceParameter, PartialEq)]
+#[derive(ChoiceParameter, PartialEq, Clone, Copy)]
 pub enum Of {
   Apps,   // Top apps in user, or guild, by hours or count
+  Guilds, // Top guilds by total or average member hours, or app count
   Users,  // Top users by app hours or app count, in gyuk
 }
 
-// Top of (Apps, Guilds, Users) by (Playtime, Ownership) at (User, Guild, App, Global)
-pub fn build_top_query(of: Of, by: By, at: At, server: i64) -> SelectStatement {
+// Top of (Apps, Guilds, Users) by (Playtime, Average, Ownership) at (User, App) in (Guild, Global)
+// Passing `None` as the guild makes the top global, across all scraped (whitelisted) guilds,
+// and passing a span makes it count only the playtime gained within it
+pub fn build_top_query(
+  of: Of,
+  by: By,
+  at: At,
+  guild: Option<i64>,
+  span: Option<Span>,
+) -> SelectStatement {
   let mut qb = Query::select();
   qb.from(super::schema::Playdata::Table);
   nekoid_eq(&mut qb);
-  member_eq(&mut qb);
 
-  //use discord::schema::{Guilds, Members};
-  //qb.from(Guilds::Table);
-  //qb.and_where(ex_col!(Guilds, Id).equals(col!(Members, GuildId)));
-  //qb.group_by_col(col!(Guilds, Id));
-  //qb.columns([col!(Guilds, Id), col!(Guilds, Name)]);
+  let playtime = match span {
+    Some(span) => {
+      use steam::schema::{Playdata, PlaydataHistory};
+      let period = Alias::new("period");
+      qb.from_subquery(period_subquery(span), period.clone());
+      qb.and_where(
+        Expr::col((period.clone(), PlaydataHistory::PlaydataId)).equals(col!(Playdata, Id)),
+      );
+      Expr::col((period, PlaydataHistory::Playtime))
+    }
+    None => ex_col!(steam::schema::Playdata, Playtime),
+  };
+  if by.is_achievement() {
+    use steam::schema::{AppAchievements, Playdata, PlaydataAchievements};
+    qb.from(PlaydataAchievements::Table);
+    qb.from(AppAchievements::Table);
+    qb.and_where(ex_col!(PlaydataAchievements, PlaydataId).equals(col!(Playdata, Id)));
+    qb.and_where(ex_col!(AppAchievements, AppId).equals(col!(Playdata, AppId)));
+    qb.and_where(ex_col!(AppAchievements, Total).gt(0));
+  }
 
   match of {
     Of::Apps => {
@@ -182,6 +806,18 @@ pub fn build_top_query(of: Of, by: By, at: At, server: i64) -> SelectStatement {
       qb.and_where(ex_col!(Apps, Id).equals(col!(Playdata, AppId)));
       qb.group_by_col(col!(Apps, Id));
       qb.columns([col!(Apps, Id), col!(Apps, Name)]);
+      member_in(&mut qb, guild);
+    }
+    Of::Guilds => {
+      use discord::schema::{Guilds, Members};
+      member_eq(&mut qb);
+      qb.from(Guilds::Table);
+      qb.and_where(ex_col!(Guilds, Id).equals(col!(Members, GuildId)));
+      qb.group_by_col(col!(Guilds, Id));
+      qb.columns([col!(Guilds, Id), col!(Guilds, Name)]);
+      if let Some(guild) = guild {
+        qb.and_where(ex_col!(Members, GuildId).eq(guild));
+      }
     }
     Of::Users => {
       use discord::schema::Users;
@@ -189,39 +825,163 @@ pub fn build_top_query(of: Of, by: By, at: At, server: i64) -> SelectStatement {
       qb.from(Users::Table);
       qb.and_where(ex_col!(Users, Id).equals(col!(UsersDiscord, DiscordId)));
       qb.group_by_col(col!(Users, Id));
-      qb.columns([col!(Users, Id), col!(Users, Name)]);
+      qb.column(col!(Users, Id));
+      qb.expr_as(discord::display_name(guild), Alias::new("name"));
+      member_in(&mut qb, guild);
     }
   }
-  {
-    use steam::schema::Playdata;
-    qb.expr_as(
-      match by {
-        By::Playtime => Func::sum(ex_col!(Playdata, Playtime)),
-        By::Ownership => Func::count(ex_col!(Playdata, AppId)),
-      },
-      Alias::new("sum_count"),
-    );
-    qb.expr_window_as(
-      Func::cust(Alias::new("ROW_NUMBER")),
-      WindowStatement::new()
-        .order_by_expr(
-          match by {
-            By::Playtime => Expr::sum(ex_col!(Playdata, Playtime)),
-            By::Ownership => Expr::count(ex_col!(Playdata, AppId)),
-          },
-          Order::Desc,
-        )
-        .to_owned(),
-      Alias::new("row_num"),
-    );
-  }
+  rank_by(&mut qb, by_expr(&of, &by, playtime));
+  at_where(&mut qb, at);
+  qb
+}
+
+// Games owned by every one of the discord users, by their combined playtime
+pub fn build_common_query(users: Vec<i64>) -> SelectStatement {
+  use neko::schema::UsersDiscord;
+  use steam::schema::{Apps, Playdata};
+  let count = users.len() as i64;
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  nekoid_eq(&mut qb);
+  qb.from(Apps::Table);
+  qb.and_where(ex_col!(Apps, Id).equals(col!(Playdata, AppId)));
+  qb.and_where(ex_col!(UsersDiscord, DiscordId).is_in(users));
+  qb.group_by_col(col!(Apps, Id));
+  qb.columns([col!(Apps, Id), col!(Apps, Name)]);
+  // Users with several linked accounts owning the same game are only counted once
+  qb.and_having(Expr::expr(Func::count_distinct(ex_col!(UsersDiscord, DiscordId))).eq(count));
+  rank_by(&mut qb, Func::sum(ex_col!(Playdata, Playtime)).into());
+  qb
+}
+
+// Selects the metric as `sum_count` along with its `row_num`, and orders by it
+fn rank_by(qb: &mut SelectStatement, metric: SimpleExpr) {
+  qb.expr_as(metric.clone(), Alias::new("sum_count"));
+  qb.expr_window_as(
+    Func::cust(Alias::new("ROW_NUMBER")),
+    WindowStatement::new()
+      .order_by_expr(metric, Order::Desc)
+      .to_owned(),
+    Alias::new("row_num"),
+  );
+  qb.order_by(Alias::new("sum_count"), Order::Desc);
+}
+
+// Daily playtime gained within the span, days without any playtime are omitted
+pub fn build_history_query(at: At, guild: Option<i64>, span: Span) -> SelectStatement {
+  use steam::schema::{Playdata, PlaydataHistory::*};
+  let deltas = Alias::new("deltas");
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  nekoid_eq(&mut qb);
+  member_in(&mut qb, guild);
+  qb.from_subquery(delta_subquery(span), deltas.clone());
+  qb.and_where(Expr::col((deltas.clone(), PlaydataId)).equals(col!(Playdata, Id)));
+  qb.and_where(Expr::col((deltas.clone(), UtcDay)).gte(span.from));
+  qb.column((deltas.clone(), UtcDay));
+  let prev = Expr::col((deltas.clone(), Alias::new("prev")));
+  qb.expr_as(
+    Func::sum(Expr::col((deltas.clone(), Playtime)).sub(prev)),
+    Playtime,
+  );
+  qb.group_by_col((deltas.clone(), UtcDay));
+  qb.order_by((deltas, UtcDay), Order::Asc);
+  at_where(&mut qb, at);
+  qb
+}
+
+fn at_where(qb: &mut SelectStatement, at: At) {
+  use neko::schema::UsersDiscord;
+  use steam::schema::Playdata;
   match at {
-    At::User(id) => qb.and_where(ex_col!(neko::schema::UsersDiscord, DiscordId).eq(id)),
-    At::App(id) => qb.and_where(ex_col!(steam::schema::Playdata, AppId).eq(id)),
-    At::None => &qb,
+    At::User(id) => qb.and_where(ex_col!(UsersDiscord, DiscordId).eq(id)),
+    At::App(id) => qb.and_where(ex_col!(Playdata, AppId).eq(id)),
+    At::UserApp(user, app) => qb
+      .and_where(ex_col!(UsersDiscord, DiscordId).eq(user))
+      .and_where(ex_col!(Playdata, AppId).eq(app)),
+    At::None => qb,
   };
-  qb.and_where(ex_col!(discord::schema::Members, GuildId).eq(server));
-  qb.order_by(Alias::new("sum_count"), Order::Desc);
+}
+
+fn by_expr(of: &Of, by: &By, playtime: Expr) -> SimpleExpr {
+  use steam::schema::{AppAchievements, Playdata, PlaydataAchievements};
+  match by {
+    By::Achievements => Func::sum(ex_col!(PlaydataAchievements, Achieved)).into(),
+    By::Completion => Expr::expr(Func::avg(
+      ex_col!(PlaydataAchievements, Achieved)
+        .mul(100)
+        .div(ex_col!(AppAchievements, Total)),
+    ))
+    .cast_as(Alias::new("BIGINT")),
+    By::Perfect => Func::sum(
+      Expr::case(
+        ex_col!(PlaydataAchievements, Achieved).equals(col!(AppAchievements, Total)),
+        1,
+      )
+      .finally(0),
+    )
+    .into(),
+    // Summing bigint period deltas yields numeric, which doesn't decode into i64
+    By::Playtime => Expr::expr(Func::sum(playtime)).cast_as(Alias::new("BIGINT")),
+    By::Ownership => Func::count(ex_col!(Playdata, AppId)).into(),
+    // Average per owner for apps, per owned app for users, and per member for guilds
+    By::Average => Expr::expr(Func::sum(playtime))
+      .div(Func::count_distinct(match of {
+        Of::Apps => ex_col!(Playdata, UserId),
+        Of::Guilds => ex_col!(discord::schema::Members, UserId),
+        Of::Users => ex_col!(Playdata, AppId),
+      }))
+      .cast_as(Alias::new("BIGINT")),
+  }
+}
+
+// History rows within the span, alongside the playtime of the previous row as `prev`.
+// The first row of an account has nothing to compare to, so it counts as no gain, while newly
+// owned apps get a zero baseline row when they are first stored
+fn delta_subquery(span: Span) -> SelectStatement {
+  use steam::schema::PlaydataHistory::*;
+  let playtime: SimpleExpr = Expr::col(Playtime).into();
+  let mut lag = Query::select();
+  lag.from(Table);
+  lag.columns([PlaydataId, UtcDay, Playtime]);
+  lag.expr_window_as(
+    Func::cust(Alias::new("LAG")).args([playtime.clone(), Expr::val(1).into(), playtime]),
+    WindowStatement::partition_by(PlaydataId)
+      .order_by(UtcDay, Order::Asc)
+      .to_owned(),
+    Alias::new("prev"),
+  );
+  // History is only written on change, so the baseline is the last row before the span,
+  // however many days ago that was. Older rows don't need to go through the window
+  let before = Alias::new("before");
+  let mut sq = Query::select();
+  sq.from_as(Table, before.clone());
+  sq.expr(Func::max(Expr::col((before.clone(), UtcDay))));
+  sq.and_where(Expr::col((before.clone(), PlaydataId)).equals((Table, PlaydataId)));
+  sq.and_where(Expr::col((before, UtcDay)).lt(span.from));
+  lag.and_where(Expr::col((Table, UtcDay)).gte(Func::coalesce([
+    SimpleExpr::SubQuery(None, Box::new(sq.into_sub_query_statement())),
+    Expr::val(span.from).into(),
+  ])));
+  lag.and_where(Expr::col(UtcDay).lte(span.to));
+  let mut qb = Query::select();
+  qb.from_subquery(lag, Alias::new("history"));
+  qb.columns([PlaydataId, UtcDay, Playtime]);
+  qb.column(Alias::new("prev"));
+  qb.and_where(Expr::col(UtcDay).gte(span.from));
+  qb
+}
+
+// Playtime gained by each playdata row within the span, rows that weren't played are omitted
+fn period_subquery(span: Span) -> SelectStatement {
+  use steam::schema::PlaydataHistory::*;
+  let delta = Expr::col(Playtime).sub(Expr::col(Alias::new("prev")));
+  let mut qb = Query::select();
+  qb.from_subquery(delta_subquery(span), Alias::new("lag"));
+  qb.column(PlaydataId);
+  qb.expr_as(Func::sum(delta.clone()), Playtime);
+  qb.group_by_col(PlaydataId);
+  qb.and_having(Expr::expr(Func::sum(delta)).gt(0));
   qb
 }
 
@@ -243,6 +1003,204 @@ fn member_eq(qb: &mut SelectStatement) {
   );
 }
 
+// Unlike member_eq, doesn't multiply rows of users that are in several guilds
+fn member_in(qb: &mut SelectStatement, guild: Option<i64>) {
+  use discord::schema::*;
+  use neko::schema::*;
+  let mut sq = Query::select();
+  sq.from(Members::Table);
+  sq.column(Members::UserId);
+  if let Some(guild) = guild {
+    sq.and_where(Expr::col(Members::GuildId).eq(guild));
+  }
+  qb.and_where(ex_col!(UsersDiscord, DiscordId).in_subquery(sq));
+}
+
+pub async fn add_role_mapping(guild: i64, role: i64, app: i64, min_playtime: i32) -> R {
+  use steam::schema::DiscordRoles::*;
+  let mut qb = Query::insert();
+  qb.into_table(Table);
+  qb.columns([GuildId, RoleId, AppId, MinPlaytime]);
+  qb.on_conflict(
+    OnConflict::columns([GuildId, AppId])
+      .update_columns([RoleId, MinPlaytime])
+      .to_owned(),
+  );
+  qb.values([guild.into(), role.into(), app.into(), min_playtime.into()])?;
+  execute!(&qb)?;
+  Ok(())
+}
+
+/// Returns the id of the role that was mapped to the app, if there was one
+pub async fn remove_role_mapping(guild: i64, app: i64) -> Res<Option<i64>> {
+  use steam::schema::DiscordRoles::*;
+  let mut qb = Query::delete();
+  qb.from_table(Table);
+  qb.and_where(Expr::col(GuildId).eq(guild));
+  qb.and_where(Expr::col(AppId).eq(app));
+  qb.returning(Query::returning().column(RoleId));
+  Ok(fetch_optional!(&qb, (i64,))?.map(|r| r.0))
+}
+
+/// Role, app, app name and min playtime of every mapping in the guild
+pub async fn role_mappings(guild: i64) -> Res<Vec<(i64, i64, String, i32)>> {
+  use steam::schema::{Apps, DiscordRoles};
+  let mut qb = Query::select();
+  qb.from(DiscordRoles::Table);
+  qb.from(Apps::Table);
+  qb.column(col!(DiscordRoles, RoleId));
+  qb.column(col!(DiscordRoles, AppId));
+  qb.column(col!(Apps, Name));
+  qb.column(col!(DiscordRoles, MinPlaytime));
+  qb.and_where(ex_col!(Apps, Id).equals(col!(DiscordRoles, AppId)));
+  qb.and_where(ex_col!(DiscordRoles, GuildId).eq(guild));
+  qb.order_by(col!(Apps, Name), Order::Asc);
+  Ok(fetch_all!(&qb, (i64, i64, String, i32))?)
+}
+
+/// Guilds that have at least one app role
+pub async fn role_guilds() -> Res<Vec<i64>> {
+  use steam::schema::DiscordRoles::*;
+  let mut qb = Query::select();
+  qb.from(Table);
+  qb.column(GuildId);
+  qb.distinct();
+  Ok(fetch_all!(&qb, (i64,))?.into_iter().map(|r| r.0).collect())
+}
+
+/// Discord id and role pairs of every app role in the guild that linked users qualify for
+pub async fn desired_roles(guild: i64) -> Res<Vec<(i64, i64)>> {
+  use neko::schema::UsersDiscord;
+  use steam::schema::{DiscordRoles, Playdata};
+  // Not filtered by scraped members, as not every member of a guild is scraped
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  qb.from(DiscordRoles::Table);
+  nekoid_eq(&mut qb);
+  qb.column(col!(UsersDiscord, DiscordId));
+  qb.column(col!(DiscordRoles, RoleId));
+  qb.distinct();
+  qb.and_where(ex_col!(DiscordRoles, GuildId).eq(guild));
+  qb.and_where(ex_col!(DiscordRoles, AppId).equals(col!(Playdata, AppId)));
+  qb.and_where(ex_col!(Playdata, Playtime).gte(ex_col!(DiscordRoles, MinPlaytime)));
+  Ok(fetch_all!(&qb, (i64, i64))?)
+}
+
+/// Discord ids of guild members that qualify for the app role
+pub async fn role_candidates(guild: i64, app: i64, min_playtime: i32) -> Res<Vec<i64>> {
+  use neko::schema::UsersDiscord;
+  use steam::schema::Playdata;
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  nekoid_eq(&mut qb);
+  member_in(&mut qb, Some(guild));
+  qb.column(col!(UsersDiscord, DiscordId));
+  qb.distinct();
+  qb.and_where(ex_col!(Playdata, AppId).eq(app));
+  qb.and_where(ex_col!(Playdata, Playtime).gte(min_playtime));
+  Ok(fetch_all!(&qb, (i64,))?.into_iter().map(|r| r.0).collect())
+}
+
+#[derive(FromRow)]
+pub struct SteamAccount {
+  pub id: i64,
+  pub name: Option<String>,
+  pub avatar: Option<String>,
+  pub profile_url: Option<String>,
+  pub last_online: Option<i64>,
+  pub state: Option<String>,
+}
+
+/// Steam accounts linked to the discord user, most played first
+pub async fn steam_accounts(discord_id: i64) -> Res<Vec<SteamAccount>> {
+  use neko::schema::{UsersDiscord, UsersSteam};
+  use steam::schema::{FetchState, Playdata, Users};
+  let mut qb = Query::select();
+  qb.from(UsersDiscord::Table);
+  qb.from(UsersSteam::Table);
+  qb.and_where(ex_col!(UsersSteam, NekoId).equals(col!(UsersDiscord, NekoId)));
+  qb.and_where(ex_col!(UsersDiscord, DiscordId).eq(discord_id));
+  qb.left_join(
+    Users::Table,
+    ex_col!(Users, Id).equals(col!(UsersSteam, SteamId)),
+  );
+  qb.left_join(
+    FetchState::Table,
+    ex_col!(FetchState, SteamId).equals(col!(UsersSteam, SteamId)),
+  );
+  qb.left_join(
+    Playdata::Table,
+    ex_col!(Playdata, UserId).equals(col!(UsersSteam, SteamId)),
+  );
+  qb.expr_as(ex_col!(UsersSteam, SteamId), Alias::new("id"));
+  qb.column(col!(Users, Name));
+  qb.column(col!(Users, Avatar));
+  qb.column(col!(Users, ProfileUrl));
+  qb.column(col!(Users, LastOnline));
+  qb.column(col!(FetchState, State));
+  qb.group_by_col(col!(UsersSteam, SteamId));
+  qb.group_by_col(col!(Users, Id));
+  qb.group_by_col(col!(FetchState, SteamId));
+  qb.order_by_expr(
+    Func::coalesce([
+      Func::sum(ex_col!(Playdata, Playtime)).into(),
+      Expr::val(0).into(),
+    ])
+    .into(),
+    Order::Desc,
+  );
+  Ok(fetch_all!(&qb, SteamAccount)?)
+}
+
+/// Playtime in minutes and owned games of every Steam account of the neko user, unlike tops
+/// these don't depend on the user being scraped in any guild
+pub async fn steam_totals(neko_id: i32) -> Res<(i64, i64)> {
+  use neko::schema::UsersSteam;
+  use steam::schema::Playdata;
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  qb.from(UsersSteam::Table);
+  qb.and_where(ex_col!(UsersSteam, SteamId).equals(col!(Playdata, UserId)));
+  qb.and_where(ex_col!(UsersSteam, NekoId).eq(neko_id));
+  qb.expr(Func::coalesce([
+    Func::sum(ex_col!(Playdata, Playtime)).into(),
+    Expr::val(0i64).into(),
+  ]));
+  qb.expr(Func::count(ex_col!(Playdata, AppId)));
+  Ok(fetch_one!(&qb, (i64, i64))?)
+}
+
+/// Names and playtime in minutes of the most played apps of the neko user
+pub async fn most_played(neko_id: i32, limit: u64) -> Res<Vec<(String, i64)>> {
+  use neko::schema::UsersSteam;
+  use steam::schema::{Apps, Playdata};
+  let mut qb = Query::select();
+  qb.from(Playdata::Table);
+  qb.from(UsersSteam::Table);
+  qb.from(Apps::Table);
+  qb.and_where(ex_col!(UsersSteam, SteamId).equals(col!(Playdata, UserId)));
+  qb.and_where(ex_col!(Apps, Id).equals(col!(Playdata, AppId)));
+  qb.and_where(ex_col!(UsersSteam, NekoId).eq(neko_id));
+  qb.and_where(ex_col!(Playdata, Playtime).gt(0));
+  qb.column(col!(Apps, Name));
+  let playtime = Alias::new("playtime");
+  qb.expr_as(Func::sum(ex_col!(Playdata, Playtime)), playtime.clone());
+  qb.group_by_col(col!(Apps, Id));
+  qb.order_by(playtime, Order::Desc);
+  qb.limit(limit);
+  Ok(fetch_all!(&qb, (String, i64))?)
+}
+
+/// Position of the user in the playtime top of the guild, or of every guild
+pub async fn user_rank(discord_id: i64, guild: Option<i64>) -> Res<Option<i64>> {
+  let top = build_top_query(Of::Users, By::Playtime, At::None, guild, None);
+  let mut qb = Query::select();
+  qb.from_subquery(top, Alias::new("top"));
+  qb.column(Alias::new("row_num"));
+  qb.and_where(Expr::col(Alias::new("id")).eq(discord_id));
+  Ok(fetch_optional!(&qb, (i64,))?.map(|r| r.0))
+}
+
 #[derive(FromRow, Clone)]
 pub struct QueryOutput {
   pub row_num: i64,
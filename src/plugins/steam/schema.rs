@@ -12,6 +12,9 @@ pub enum Users {
   Name,
   Avatar,
   LastOnline,
+  ProfileUrl,
+  // Community visibility state, 1 is private and 3 is public
+  Visibility,
 }
 
 #[derive(Iden)]
@@ -20,6 +23,12 @@ pub enum Apps {
   Table,
   Id,
   Name,
+  // One of game, dlc, software, video or hardware, unset for apps only known from playdata
+  AppType,
+  ReleaseDay,
+  LastModified,
+  // Last fetch of the store page details
+  DetailsAt,
 }
 
 #[derive(Iden)]
@@ -49,5 +58,120 @@ pub enum DiscordRoles {
   GuildId,
   RoleId,
   AppId,
+  // Minimum playtime in minutes, to be granted the role
+  MinPlaytime,
 }
 
+#[derive(Iden)]
+#[iden(rename = "steam_app_achievements")]
+pub enum AppAchievements {
+  Table,
+  AppId,
+  Total,
+  CheckedAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_playdata_achievements")]
+pub enum PlaydataAchievements {
+  Table,
+  PlaydataId,
+  Achieved,
+  // Playtime at the moment of the last update, to skip apps that weren't played since
+  Playtime,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_fetch_state")]
+pub enum FetchState {
+  Table,
+  SteamId,
+  State,
+  Error,
+  CheckedAt,
+  // Whether the owner was already told how to make their game details public
+  Notified,
+  // Last fetch of every owned game, as opposed to only the recently played ones
+  FullAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_update_runs")]
+pub enum UpdateRuns {
+  Table,
+  Kind,
+  StartedAt,
+  // Unset while the run is in progress, or if it crashed
+  FinishedAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_milestone_settings")]
+pub enum MilestoneSettings {
+  Table,
+  GuildId,
+  ChannelId,
+  Hours,
+  Games,
+  Leaders,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_milestone_optout")]
+pub enum MilestoneOptout {
+  Table,
+  DiscordId,
+}
+
+// Last known #1 by playtime of each app in each guild
+#[derive(Iden)]
+#[iden(rename = "steam_app_leaders")]
+pub enum AppLeaders {
+  Table,
+  GuildId,
+  AppId,
+  DiscordId,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_news_subscriptions")]
+pub enum NewsSubscriptions {
+  Table,
+  ChannelId,
+  AppId,
+  GuildId,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_news_seen")]
+pub enum NewsSeen {
+  Table,
+  AppId,
+  Gid,
+  PublishedAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_price_history")]
+pub enum PriceHistory {
+  Table,
+  AppId,
+  Region,
+  Currency,
+  Initial,
+  Price,
+  Discount,
+  CheckedAt,
+}
+
+#[derive(Iden)]
+#[iden(rename = "steam_price_watches")]
+pub enum PriceWatches {
+  Table,
+  DiscordId,
+  AppId,
+  Region,
+  Target,
+  ChannelId,
+  Alerted,
+}
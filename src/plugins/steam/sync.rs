@@ -0,0 +1,195 @@
+// Copyright 2024 Atakku <https://atakku.dev>
+//
+// This project is dual licensed under MIT and Apache.
+
+use super::{
+  get_roles,
+  query::{desired_roles, role_guilds, role_mappings},
+};
+use crate::{
+  core::*,
+  modules::poise::try_sctx,
+  plugins::{gwaaa::get_mc_users, neko::query::discord_connections},
+};
+use futures::StreamExt;
+use poise::serenity_prelude::{Context, GuildId, Member, RoleId, UserId};
+use std::collections::{HashMap, HashSet};
+
+pub const MCROLE: RoleId = RoleId(1341770285082214462);
+
+/// Changes to the bot managed roles of a single member
+pub struct RoleDiff {
+  pub user: UserId,
+  pub add: Vec<RoleId>,
+  pub remove: Vec<RoleId>,
+}
+
+impl RoleDiff {
+  pub fn is_empty(&self) -> bool {
+    self.add.is_empty() && self.remove.is_empty()
+  }
+}
+
+/// Guilds with app roles, and any cached guild that has the Minecraft role
+async fn managed_guilds(c: &Context) -> Res<Vec<GuildId>> {
+  let mut guilds: HashSet<_> = role_guilds()
+    .await?
+    .into_iter()
+    .map(|g| GuildId(g as u64))
+    .collect();
+  for guild in c.cache.guilds() {
+    if c.cache.role(guild, MCROLE).is_some() {
+      guilds.insert(guild);
+    }
+  }
+  Ok(guilds.into_iter().collect())
+}
+
+/// Roles that the bot grants and revokes in the guild, any other role is left untouched
+async fn managed_roles(c: &Context, guild: GuildId) -> Res<HashSet<RoleId>> {
+  let mut roles: HashSet<_> = role_mappings(guild.0 as i64)
+    .await?
+    .into_iter()
+    .map(|r| RoleId(r.0 as u64))
+    .collect();
+  if c.cache.role(guild, MCROLE).is_some() {
+    roles.insert(MCROLE);
+  }
+  Ok(roles)
+}
+
+fn diff(member: &Member, managed: &HashSet<RoleId>, desired: &HashSet<RoleId>) -> RoleDiff {
+  let actual: HashSet<_> = member
+    .roles
+    .iter()
+    .filter(|r| managed.contains(r))
+    .copied()
+    .collect();
+  RoleDiff {
+    user: member.user.id,
+    add: desired.difference(&actual).copied().collect(),
+    remove: actual.difference(desired).copied().collect(),
+  }
+}
+
+// Applies the whole diff with a single member edit, instead of a request per role
+async fn apply(c: &Context, member: &mut Member, diff: &RoleDiff) -> R {
+  if diff.is_empty() {
+    return Ok(());
+  }
+  let roles: Vec<_> = member
+    .roles
+    .iter()
+    .filter(|r| !diff.remove.contains(r))
+    .chain(diff.add.iter())
+    .copied()
+    .collect();
+  *member = member.edit(c, |m| m.roles(roles)).await?;
+  Ok(())
+}
+
+/// Reconcile managed roles of every guild member, only computing the changes if `dry_run` is set
+pub async fn sync_guild(c: &Context, guild: GuildId, dry_run: bool) -> Res<Vec<RoleDiff>> {
+  let managed = managed_roles(c, guild).await?;
+  if managed.is_empty() {
+    return Ok(vec![]);
+  }
+  let mut desired: HashMap<UserId, HashSet<RoleId>> = HashMap::new();
+  for (user, role) in desired_roles(guild.0 as i64).await? {
+    let roles = desired.entry(UserId(user as u64)).or_default();
+    roles.insert(RoleId(role as u64));
+  }
+  if managed.contains(&MCROLE) {
+    for user in get_mc_users().await? {
+      desired.entry(user).or_default().insert(MCROLE);
+    }
+  }
+
+  let none = HashSet::new();
+  let mut diffs = vec![];
+  let mut members = guild.members_iter(c).boxed();
+  while let Some(member) = members.next().await {
+    let Ok(mut member) = member else {
+      continue;
+    };
+    if member.user.bot {
+      continue;
+    }
+    let diff = diff(
+      &member,
+      &managed,
+      desired.get(&member.user.id).unwrap_or(&none),
+    );
+    if diff.is_empty() {
+      continue;
+    }
+    if !dry_run {
+      if let Err(err) = apply(c, &mut member, &diff).await {
+        log::warn!(
+          "Failed to sync roles of {} in {guild}: {err}",
+          member.user.id
+        );
+        continue;
+      }
+    }
+    diffs.push(diff);
+  }
+  Ok(diffs)
+}
+
+pub async fn sync_member(c: &Context, member: &mut Member) -> Res<RoleDiff> {
+  let managed = managed_roles(c, member.guild_id).await?;
+  let mut desired: HashSet<_> = get_roles(member).await?.into_iter().collect();
+  if managed.contains(&MCROLE) && get_mc_users().await?.contains(&member.user.id) {
+    desired.insert(MCROLE);
+  }
+  let diff = diff(member, &managed, &desired);
+  apply(c, member, &diff).await?;
+  Ok(diff)
+}
+
+/// Reconcile roles of a user in every managed guild they're in, used when accounts get (un)linked
+pub async fn sync_user(c: &Context, user: UserId) -> R {
+  for guild in managed_guilds(c).await? {
+    if let Ok(mut member) = guild.member(c, user).await {
+      sync_member(c, &mut member).await?;
+    }
+  }
+  Ok(())
+}
+
+pub async fn sync_neko_user(neko_id: i32) -> R {
+  // Roles get reconciled by the hourly sync anyway, if Discord isn't connected yet
+  let Some(c) = try_sctx() else {
+    log::warn!("Discord isn't connected yet, skipping role sync of neko user {neko_id}");
+    return Ok(());
+  };
+  for (discord_id,) in discord_connections(neko_id).await? {
+    sync_user(c, UserId(discord_id as u64)).await?;
+  }
+  Ok(())
+}
+
+pub async fn sync_all(c: &Context) -> R {
+  for guild in managed_guilds(c).await? {
+    let diffs = sync_guild(c, guild, false).await?;
+    log::info!("Synced roles of {} members in {guild}", diffs.len());
+  }
+  Ok(())
+}
+
+/// Revoke a role that is no longer managed, from every member that still has it
+pub async fn revoke_role(c: &Context, guild: GuildId, role: RoleId) -> Res<usize> {
+  let mut revoked = 0;
+  let mut members = guild.members_iter(c).boxed();
+  while let Some(member) = members.next().await {
+    let Ok(mut member) = member else {
+      continue;
+    };
+    if member.roles.contains(&role) {
+      member.remove_role(c, role).await?;
+      revoked += 1;
+    }
+  }
+  Ok(revoked)
+}